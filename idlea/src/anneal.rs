@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use rand::Rng;
+
+use crate::game_state::GameState;
+use crate::objective::{Checkpoints, Objective};
+use crate::schedule;
+use crate::upg_seq::{self, Operators};
+
+const REPORT_EVERY: usize = 1000; // iterations between progress lines
+
+/// How the annealing temperature falls from `start` to `end`, in score units.
+#[derive(Debug)]
+pub struct Cooling {
+    pub start: f64,
+    pub end: f64,
+    pub linear: bool,
+}
+
+impl Cooling {
+    /// Parses `--temp` as `<start>:<end>` and `--cooling` as geometric or linear.
+    pub fn from_args(temp: &str, cooling: &str) -> Result<Cooling, String> {
+        let bad = || format!("bad temperatures {:?}, use e.g. 0.5:0.005", temp);
+        let mut ends = temp.splitn(2, ':');
+        let start: f64 = ends.next().and_then(|t| t.parse().ok()).ok_or_else(bad)?;
+        let end: f64 = ends.next().and_then(|t| t.parse().ok()).ok_or_else(bad)?;
+        let linear = match cooling {
+            "geometric" => false,
+            "linear" => true,
+            _ => {
+                return Err(format!(
+                    "unknown cooling {:?}, use geometric or linear",
+                    cooling
+                ))
+            }
+        };
+        if end < 0.0 || start < end || (!linear && end <= 0.0) {
+            return Err(bad());
+        }
+        Ok(Cooling { start, end, linear })
+    }
+
+    /// The temperature once `frac` (0 to 1) of the iterations are done.
+    pub fn temp(&self, frac: f64) -> f64 {
+        if self.linear {
+            self.start + (self.end - self.start) * frac
+        } else {
+            self.start * (self.end / self.start).powf(frac)
+        }
+    }
+}
+
+/// Simulated annealing over the same neighbours `improve_main` tries: a random variation of
+/// the current sequence, from `upg_seq::random_variation`, is always taken if it scores at
+/// least as well, and otherwise with probability exp(-loss / temperature).
+#[allow(clippy::too_many_arguments)]
+pub fn anneal_main<O: Objective>(
+    gs: GameState,
+    initial_moves_file: &str,
+    output_file: &str,
+    iters: usize,
+    cooling: &Cooling,
+    pushy: bool,
+    ops: Operators,
+    lenient: bool,
+    obj: O,
+) -> Result<(), Box<dyn Error>> {
+    let g = gs.g;
+    let seq = upg_seq::load_sequence(initial_moves_file, &gs, lenient)?;
+    let mut scratchpad = GameState::new_from_game(g);
+    let mut rng = rand::thread_rng();
+
+    let mut cur_score = obj.score(&gs, &mut scratchpad, &seq);
+    println!(
+        "{} moves in initial sequence, score {:.3} {}",
+        seq.len(),
+        cur_score,
+        obj.describe()
+    );
+    let mut best_score = cur_score;
+    let mut best_seq = seq.clone();
+    let mut cur_seq = seq;
+    let mut checkpoints = Checkpoints::new(&gs, &cur_seq, &obj);
+    let mut accepted = 0;
+
+    for it in 0..iters {
+        let new_seq = match upg_seq::random_variation(&cur_seq, &gs, pushy, ops, &mut rng) {
+            Some(new_seq) => new_seq,
+            None => {
+                println!("No variations to try");
+                break;
+            }
+        };
+        let temp = cooling.temp(it as f64 / iters as f64);
+        let s = checkpoints.score(&obj, &mut scratchpad, &new_seq);
+        if s >= cur_score || rng.gen::<f64>() < ((s - cur_score) / temp).exp() {
+            cur_seq = new_seq;
+            cur_score = s;
+            accepted += 1;
+            if s > best_score {
+                println!("i{}: New best score {:.3} (T={:.4})", it, s, temp);
+                best_score = s;
+                best_seq = cur_seq.clone();
+                if !output_file.is_empty() {
+                    let mut file = File::create(output_file)?;
+                    for mv in &best_seq {
+                        writeln!(&mut file, "{}", mv.to_string(g))?;
+                    }
+                }
+            }
+            checkpoints = Checkpoints::new(&gs, &cur_seq, &obj);
+        }
+        if (it + 1) % REPORT_EVERY == 0 {
+            println!(
+                "i{}: T={:.4} current {:.3} best {:.3}, {} accepted",
+                it + 1,
+                temp,
+                cur_score,
+                best_score,
+                accepted
+            );
+        }
+    }
+    println!("Best score {:.3} {}", best_score, obj.describe());
+    schedule::print_sessions(&gs, &best_seq);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooling() {
+        let geom = Cooling::from_args("1:0.01", "geometric").unwrap();
+        assert_eq!(geom.temp(0.0), 1.0);
+        assert!((geom.temp(0.5) - 0.1).abs() < 1e-12);
+        assert!((geom.temp(1.0) - 0.01).abs() < 1e-12);
+        let lin = Cooling::from_args("1:0", "linear").unwrap();
+        assert_eq!(lin.temp(0.5), 0.5);
+
+        assert!(Cooling::from_args("1:0", "geometric").is_err());
+        assert!(Cooling::from_args("0.1:1", "linear").is_err());
+        assert!(Cooling::from_args("1", "linear").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Game, Move};
+use crate::game_state::GameState;
+use crate::upg_seq;
+
+/// A plan worth keeping, and the random seed it started from, if any.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Saved {
+    pub score: f64,
+    pub seed: Option<u64>,
+    pub moves: Vec<Move>,
+}
+
+/// How different two plans are: the average number of places each move is moved from one plan
+/// to the other. A move that's only in one of them (or is there more times, for switches)
+/// counts as moved one place, so adding a few switches doesn't make a plan new.
+pub fn distance(a: &[Move], b: &[Move]) -> f64 {
+    let positions = |seq: &[Move]| {
+        let mut seen: HashMap<Move, usize> = HashMap::new();
+        let mut pos = HashMap::new();
+        for (i, mv) in seq.iter().enumerate() {
+            let nth = seen.entry(*mv).or_insert(0);
+            *nth += 1;
+            pos.insert((*mv, *nth), i);
+        }
+        pos
+    };
+    let (pos_a, pos_b) = (positions(a), positions(b));
+    let mut total = 0;
+    let mut moves = 0;
+    for (key, &i) in &pos_a {
+        total += pos_b.get(key).map_or(1, |&j| i.abs_diff(j));
+        moves += 1;
+    }
+    for key in pos_b.keys() {
+        if !pos_a.contains_key(key) {
+            total += 1;
+            moves += 1;
+        }
+    }
+    if moves == 0 {
+        0.0
+    } else {
+        total as f64 / moves as f64
+    }
+}
+
+/// The best `size` plans that are at least `min_distance` apart, kept in `dir` if it's given.
+pub struct Archive {
+    pub dir: String,
+    pub size: usize,
+    pub min_distance: f64,
+}
+
+impl Archive {
+    /// Adds `saved` to `plans` (best first) if it makes the cut and no better plan is close to
+    /// it, dropping any worse plans close to it. Returns whether `plans` changed.
+    pub fn add(&self, plans: &mut Vec<Saved>, saved: Saved) -> bool {
+        let close = |p: &Saved| distance(&p.moves, &saved.moves) < self.min_distance;
+        if plans.iter().any(|p| p.score >= saved.score && close(p)) {
+            return false;
+        }
+        // Any close plans left are worse, so `saved` ranks above them and is kept.
+        plans.retain(|p| !close(p));
+        let at = plans
+            .iter()
+            .position(|p| p.score < saved.score)
+            .unwrap_or(plans.len());
+        if at >= self.size {
+            return false;
+        }
+        plans.insert(at, saved);
+        plans.truncate(self.size);
+        true
+    }
+
+    /// Replaces the plans in `dir` with `plans`, named by rank and score. Ranks are padded to
+    /// the same width so the names sort best first.
+    pub fn write(&self, plans: &[Saved], g: &Game) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if is_plan_file(&path) {
+                fs::remove_file(path)?;
+            }
+        }
+        let width = self.size.max(plans.len()).to_string().len().max(2);
+        for (rank, saved) in plans.iter().enumerate() {
+            let name = format!("plan_{:0w$}_{:.4}.txt", rank + 1, saved.score, w = width);
+            let file = Path::new(&self.dir).join(name);
+            let mut file = File::create(file)?;
+            for mv in &saved.moves {
+                writeln!(&mut file, "{}", mv.to_string(g))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The plans in `dir`, in file name order, so best first for an archive `write` made.
+    pub fn load(&self, gs: &GameState, lenient: bool) -> Result<Vec<Vec<Move>>, Box<dyn Error>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if is_plan_file(&path) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        if files.is_empty() {
+            return Err(format!("no plan_*.txt files in {}", self.dir).into());
+        }
+        let mut plans = Vec::new();
+        for file in files {
+            plans.push(upg_seq::load_sequence(file.to_str().unwrap(), gs, lenient)?);
+        }
+        Ok(plans)
+    }
+}
+
+fn is_plan_file(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    name.starts_with("plan_") && name.ends_with(".txt")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::LvlUp;
+
+    fn plan(uidxs: &[usize]) -> Vec<Move> {
+        uidxs
+            .iter()
+            .map(|&uidx| Move::LvlUp(LvlUp { uidx, level: 1 }))
+            .collect()
+    }
+
+    #[test]
+    fn test_distance() {
+        let a = plan(&[0, 1, 2, 3]);
+        assert_eq!(distance(&a, &a), 0.0);
+        assert_eq!(distance(&a, &plan(&[1, 0, 2, 3])), 0.5);
+        assert_eq!(distance(&a, &plan(&[3, 0, 1, 2])), 1.5);
+        assert_eq!(distance(&a, &plan(&[0, 1, 2])), 0.25);
+    }
+
+    #[test]
+    fn test_archive_keeps_distinct() {
+        let archive = Archive {
+            dir: String::new(),
+            size: 2,
+            min_distance: 1.0,
+        };
+        let saved = |score, uidxs: &[usize]| Saved {
+            score,
+            seed: None,
+            moves: plan(uidxs),
+        };
+        let mut plans = Vec::new();
+        assert!(archive.add(&mut plans, saved(1.0, &[0, 1, 2, 3])));
+        assert!(!archive.add(&mut plans, saved(0.9, &[1, 0, 2, 3])));
+        assert!(archive.add(&mut plans, saved(1.1, &[1, 0, 2, 3])));
+        assert_eq!(plans.len(), 1);
+        assert!(archive.add(&mut plans, saved(0.5, &[3, 2, 1, 0])));
+        assert!(!archive.add(&mut plans, saved(0.4, &[2, 3, 0, 1])));
+        assert!(archive.add(&mut plans, saved(2.0, &[2, 3, 0, 1])));
+        let scores: Vec<f64> = plans.iter().map(|p| p.score).collect();
+        assert_eq!(scores, vec![2.0, 1.1]);
+    }
+
+    #[test]
+    fn test_write_sorts_by_rank() {
+        let dir = std::env::temp_dir().join("idlea_test_archive");
+        let _ = fs::remove_dir_all(&dir);
+        let archive = Archive {
+            dir: dir.to_str().unwrap().to_string(),
+            size: 120,
+            min_distance: 1.0,
+        };
+        let plans: Vec<Saved> = (0..110)
+            .map(|i| Saved {
+                score: 200.0 - i as f64,
+                seed: None,
+                moves: plan(&[0]),
+            })
+            .collect();
+        archive.write(&plans, &Game::new()).unwrap();
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 110);
+        assert_eq!(names[0], "plan_001_200.0000.txt");
+        assert_eq!(names[99], "plan_100_101.0000.txt");
+        assert_eq!(names[109], "plan_110_91.0000.txt");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
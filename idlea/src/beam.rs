@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use hashbrown::HashMap;
+
+use crate::game::{LvlUp, Move};
+use crate::game_state::GameState;
+use crate::objective::Objective;
+use crate::relax;
+use crate::schedule;
+use crate::upg_seq;
+
+/// A partial plan in the beam: the moves so far and where they leave the game.
+struct Node<'a> {
+    gs: GameState<'a>,
+    seq: Vec<Move>,
+    value: f64,
+}
+
+/// What a beam search found: the best finished plan, completed, and what the objective makes
+/// of it, with how many steps the search took and how many plans it finished.
+pub struct Beamed {
+    pub seq: Vec<Move>,
+    pub score: f64,
+    pub steps: usize,
+    pub finished: usize,
+}
+
+/// Builds a plan by beam search and writes it to `output_file` for `imp` to polish.
+pub fn beam_main<O: Objective>(
+    gs: GameState,
+    output_file: &str,
+    width: usize,
+    obj: O,
+) -> Result<(), Box<dyn Error>> {
+    if width == 0 {
+        return Err("beam width must be at least 1".into());
+    }
+    let g = gs.g;
+    let beamed = beam(&gs, width, &obj);
+    println!(
+        "Beam search took {} steps, {} plans finished",
+        beamed.steps, beamed.finished
+    );
+    let best_seq = beamed.seq;
+    println!(
+        "Best plan has {} moves, score {:.3} {}",
+        best_seq.len(),
+        beamed.score,
+        obj.describe()
+    );
+    if !output_file.is_empty() {
+        let mut file = File::create(output_file)?;
+        for mv in &best_seq {
+            writeln!(&mut file, "{}", mv.to_string(g))?;
+        }
+        println!("Moves written to {}", output_file);
+    }
+    schedule::print_sessions(&gs, &best_seq);
+    Ok(())
+}
+
+/// Each step tries every affordable level up from each plan in the beam and keeps the `width`
+/// best, ranked by the points each would have at the end of the event at its current rates.
+/// Plans that can't make another move before the event ends are finished; the one `obj` likes
+/// best, with the levels it hasn't reached tacked on the end, is returned.
+pub fn beam<O: Objective>(gs: &GameState, width: usize, obj: &O) -> Beamed {
+    let g = gs.g;
+    let mut start = GameState::new_from_game(g);
+    start.copy_from(gs);
+    start.update_rates();
+    let mut beam = vec![Node {
+        value: 0.0,
+        gs: start,
+        seq: Vec::new(),
+    }];
+    let mut finished = Vec::new();
+    let mut steps = 0;
+    let mut scratchpad = GameState::new_from_game(g);
+    while !beam.is_empty() {
+        let mut children: Vec<Node> = Vec::new();
+        // Plans reaching the same levels differ only in timing, so keep the best of them.
+        let mut seen: HashMap<Vec<usize>, usize> = HashMap::new();
+        for node in beam {
+            let mut expanded = false;
+            for (uidx, upg) in g.upgrades.iter().enumerate() {
+                let level = node.gs.levels[uidx] + 1;
+                let mv = Move::LvlUp(LvlUp { uidx, level });
+                if level > upg.costs().len() || !upg_seq::ready(&node.gs, &mv) {
+                    continue;
+                }
+                let mut child = GameState::new_from_game(g);
+                child.copy_from(&node.gs);
+                if !upg_seq::play_move(&mut child, &mv) || child.time >= g.event_time {
+                    continue;
+                }
+                expanded = true;
+                let mut seq = node.seq.clone();
+                seq.push(mv);
+                let value = relax::points_by(&child, g.event_time);
+                let fresh = Node {
+                    gs: child,
+                    seq,
+                    value,
+                };
+                match seen.get(&fresh.gs.levels) {
+                    Some(&i) if children[i].value >= value => {}
+                    Some(&i) => children[i] = fresh,
+                    None => {
+                        seen.insert(fresh.gs.levels.clone(), children.len());
+                        children.push(fresh);
+                    }
+                }
+            }
+            if !expanded {
+                finished.push(node.seq);
+            }
+        }
+        children.sort_by(|a, b| b.value.partial_cmp(&a.value).unwrap());
+        children.truncate(width);
+        beam = children;
+        steps += 1;
+    }
+    let nfinished = finished.len();
+    let mut best_seq = Vec::new();
+    let mut best_score = f64::NEG_INFINITY;
+    for seq in finished {
+        let seq = complete(gs, seq);
+        let s = obj.score(gs, &mut scratchpad, &seq);
+        if s > best_score {
+            best_score = s;
+            best_seq = seq;
+        }
+    }
+    Beamed {
+        seq: best_seq,
+        score: best_score,
+        steps,
+        finished: nfinished,
+    }
+}
+
+/// Adds every level `seq` doesn't reach to the end, so `imp` can look for time to fit them in.
+fn complete(gs: &GameState, mut seq: Vec<Move>) -> Vec<Move> {
+    let mut levels = gs.levels.clone();
+    for mv in &seq {
+        if let Move::LvlUp(lvlup) = mv {
+            levels[lvlup.uidx] = lvlup.level;
+        }
+    }
+    for (uidx, upg) in gs.g.upgrades.iter().enumerate() {
+        for level in levels[uidx] + 1..=upg.costs().len() {
+            seq.push(Move::LvlUp(LvlUp { uidx, level }));
+        }
+    }
+    upg_seq::repair(&seq, gs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objective::Spare;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_complete() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let seq = vec![Move::LvlUp(LvlUp { uidx: 1, level: 1 })];
+        let full = complete(&gs, seq.clone());
+        assert_eq!(full[0], seq[0]);
+        let levels: usize = g.upgrades.iter().map(|u| u.costs().len()).sum();
+        let done: usize = gs.levels.iter().sum();
+        assert_eq!(full.len(), levels - done);
+        assert_eq!(upg_seq::repair(&full, &gs), full);
+        assert!(!upg_seq::ready(
+            &gs,
+            &Move::LvlUp(LvlUp { uidx: 2, level: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_wider_no_worse() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        let narrow = beam(&gs, 5, &Spare);
+        let wide = beam(&gs, 20, &Spare);
+        let mut scratch = GameState::new_from_game(&g);
+        assert_eq!(
+            upg_seq::score_spare(&gs, &mut scratch, &narrow.seq),
+            narrow.score
+        );
+        assert_eq!(
+            upg_seq::score_spare(&gs, &mut scratch, &wide.seq),
+            wide.score
+        );
+        assert!(wide.score >= narrow.score);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use hashbrown::HashMap;
+
+use crate::game::Move;
+use crate::game_state::GameState;
+
+const SHARDS: usize = 16; // separately locked parts, so threads rarely wait on each other
+const CAPACITY: usize = 1 << 20; // scores kept in all; the oldest go first
+
+struct Shard {
+    scores: HashMap<u64, f64>,
+    order: VecDeque<u64>, // keys oldest first
+}
+
+/// Scores of sequences already played, so the optimizers don't play them again. Keys are a
+/// hash of the starting game state and the moves, so one cache can serve several states, but
+/// only one objective.
+pub struct ScoreCache {
+    shards: Vec<Mutex<Shard>>,
+    lookups: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl Default for ScoreCache {
+    fn default() -> Self {
+        ScoreCache {
+            shards: (0..SHARDS)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        scores: HashMap::new(),
+                        order: VecDeque::new(),
+                    })
+                })
+                .collect(),
+            lookups: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ScoreCache {
+    /// A key for playing `seq` from `gs`.
+    pub fn key(gs: &GameState, seq: &[Move]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        gs.levels.hash(&mut hasher);
+        gs.prod2.hash(&mut hasher);
+        gs.gem_boost.hash(&mut hasher);
+        gs.checkins.hash(&mut hasher);
+        for x in gs
+            .res_amt
+            .iter()
+            .chain(&[gs.time, gs.points, gs.commercial_mod, gs.last_checkin])
+        {
+            x.to_bits().hash(&mut hasher);
+        }
+        seq.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn shard(&self, key: u64) -> &Mutex<Shard> {
+        &self.shards[key as usize % SHARDS]
+    }
+
+    /// The score of `seq` from `gs`: the cached one if there is one, or else `score()`, which
+    /// is then cached.
+    pub fn score_with<F: FnOnce() -> f64>(&self, gs: &GameState, seq: &[Move], score: F) -> f64 {
+        let key = ScoreCache::key(gs, seq);
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        if let Some(&s) = self.shard(key).lock().unwrap().scores.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return s;
+        }
+        let s = score();
+        let mut shard = self.shard(key).lock().unwrap();
+        if shard.scores.insert(key, s).is_none() {
+            shard.order.push_back(key);
+            if shard.order.len() > CAPACITY / SHARDS {
+                let oldest = shard.order.pop_front().unwrap();
+                shard.scores.remove(&oldest);
+            }
+        }
+        s
+    }
+
+    /// Percentage of lookups so far that found a score, and how many lookups there were.
+    pub fn hit_rate(&self) -> (f64, u64) {
+        let lookups = self.lookups.load(Ordering::Relaxed);
+        let hits = self.hits.load(Ordering::Relaxed);
+        let pct = if lookups == 0 {
+            0.0
+        } else {
+            100.0 * hits as f64 / lookups as f64
+        };
+        (pct, lookups)
+    }
+
+    /// The hit rate, for progress lines.
+    pub fn describe(&self) -> String {
+        let (pct, lookups) = self.hit_rate();
+        format!("cache hits {:.1}% of {}", pct, lookups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::LvlUp;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_score_cache() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let mut later = GameState::new_from_game(&g);
+        later.time = 60.0;
+        let seq = vec![Move::LvlUp(LvlUp { uidx: 0, level: 2 })];
+        let cache = ScoreCache::default();
+        assert_eq!(cache.score_with(&gs, &seq, || 1.0), 1.0);
+        assert_eq!(cache.score_with(&gs, &seq, || 2.0), 1.0);
+        assert_eq!(cache.score_with(&later, &seq, || 3.0), 3.0);
+        assert_eq!(cache.score_with(&gs, &seq[..0], || 4.0), 4.0);
+        assert_eq!(cache.hit_rate(), (25.0, 4));
+    }
+}
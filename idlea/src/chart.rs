@@ -0,0 +1,252 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::game_state::GameState;
+use crate::trace::{Trace, TraceStep};
+use crate::upg_seq;
+
+const WIDTH: f64 = 1200.0;
+const HEIGHT: f64 = 700.0;
+const MARGIN_L: f64 = 70.0;
+const MARGIN_R: f64 = 130.0; // room for the legend
+const MARGIN_T: f64 = 20.0;
+const MARGIN_B: f64 = 50.0;
+const SEGMENT_SAMPLES: usize = 8; // points per move segment, so log curves look smooth
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#17becf", "#bcbd22",
+];
+
+/// A (time, value) point on a curve.
+type Sample = (f64, f64);
+
+pub fn chart_main(
+    gs: GameState,
+    moves_file: &str,
+    output_file: &str,
+    lenient: bool,
+) -> Result<(), Box<dyn Error>> {
+    let seq = upg_seq::load_sequence(moves_file, &gs, lenient)?;
+    let mut trace = Trace::new(&gs, &seq);
+    let steps: Vec<TraceStep> = trace.by_ref().collect();
+    if steps.len() < seq.len() {
+        println!("Move #{} can't be afforded, chart stops there", steps.len());
+    }
+    let mut end = GameState::new_from_game(gs.g);
+    end.copy_from(trace.state());
+    end.finish();
+
+    let mut out = BufWriter::new(File::create(output_file)?);
+    write_svg(&mut out, &gs, &steps, &end)?;
+    println!("Chart written to {}", output_file);
+    Ok(())
+}
+
+/// Draws points and resource amounts against time on one log axis, with a marker for every
+/// move and a line at the goal. `start` and `end` are the states before the first move and at
+/// the end of the event.
+pub fn write_svg(
+    out: &mut dyn Write,
+    start: &GameState,
+    steps: &[TraceStep],
+    end: &GameState,
+) -> std::io::Result<()> {
+    let g = start.g;
+
+    // Resource curves get two samples at each move, before and after paying its cost.
+    let mut points = vec![(start.time, start.points)];
+    let mut res: Vec<Vec<Sample>> = start
+        .res_amt
+        .iter()
+        .map(|&a| vec![(start.time, a)])
+        .collect();
+    let mut prev_t = start.time;
+    let mut prev_pts = start.points;
+    let mut prev_res = start.res_amt.clone();
+    for st in steps {
+        for i in 1..=SEGMENT_SAMPLES {
+            let f = i as f64 / SEGMENT_SAMPLES as f64;
+            let t = prev_t + f * (st.time - prev_t);
+            points.push((t, prev_pts + f * (st.points - prev_pts)));
+            for ires in 0..g.nres {
+                let amt = prev_res[ires] + f * (st.res_before[ires] - prev_res[ires]);
+                res[ires].push((t, amt));
+            }
+        }
+        for (samples, &amt) in res.iter_mut().zip(st.res_after.iter()) {
+            samples.push((st.time, amt));
+        }
+        prev_t = st.time;
+        prev_pts = st.points;
+        prev_res.copy_from_slice(&st.res_after);
+    }
+    for i in 1..=SEGMENT_SAMPLES {
+        let f = i as f64 / SEGMENT_SAMPLES as f64;
+        let t = prev_t + f * (end.time - prev_t);
+        points.push((t, prev_pts + f * (end.points - prev_pts)));
+        for ires in 0..g.nres {
+            res[ires].push((t, prev_res[ires] + f * (end.res_amt[ires] - prev_res[ires])));
+        }
+    }
+
+    let t_max = g.event_time;
+    let v_max = points
+        .iter()
+        .chain(res.iter().flatten())
+        .map(|s| s.1)
+        .fold(g.goal, f64::max);
+    let decades = v_max.max(10.0).log10().ceil() as i32;
+    let x = |t: f64| MARGIN_L + t / t_max * (WIDTH - MARGIN_L - MARGIN_R);
+    let y = |v: f64| {
+        let frac = v.max(1.0).log10() / decades as f64;
+        HEIGHT - MARGIN_B - frac * (HEIGHT - MARGIN_T - MARGIN_B)
+    };
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="11">"#,
+        WIDTH, HEIGHT
+    )?;
+    writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#)?;
+    writeln!(out, "<title>{}</title>", escape(&g.name))?;
+
+    // Axes and grid
+    let (x0, x1, y0, y1) = (x(0.0), x(t_max), y(1.0), MARGIN_T);
+    let hours = (t_max / 3600.0).round() as i32;
+    let step_h = if hours > 24 { 6 } else { 2 };
+    for h in (0..=hours).step_by(step_h as usize) {
+        let xh = x(h as f64 * 3600.0);
+        writeln!(
+            out,
+            r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#ddd"/><text x="{:.1}" y="{:.1}" text-anchor="middle">{}h</text>"##,
+            xh,
+            y0,
+            xh,
+            y1,
+            xh,
+            y0 + 15.0,
+            h
+        )?;
+    }
+    for d in 0..=decades {
+        let yd = y(10f64.powi(d));
+        writeln!(
+            out,
+            r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#ddd"/><text x="{:.1}" y="{:.1}" text-anchor="end">1e{}</text>"##,
+            x0,
+            yd,
+            x1,
+            yd,
+            x0 - 5.0,
+            yd + 4.0,
+            d
+        )?;
+    }
+    writeln!(
+        out,
+        r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">hours into event</text>"#,
+        (x0 + x1) / 2.0,
+        HEIGHT - 10.0
+    )?;
+
+    // Move markers
+    for st in steps {
+        let xm = x(st.time);
+        writeln!(
+            out,
+            r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#bbb" stroke-dasharray="2,3"/>"##,
+            xm, y0, xm, y1
+        )?;
+        writeln!(
+            out,
+            r##"<text transform="translate({:.1},{:.1}) rotate(-90)" font-size="8" fill="#666">{} {}</text>"##,
+            xm - 2.0,
+            y0 - 4.0,
+            escape(&st.upgrade),
+            st.level
+        )?;
+    }
+
+    // Goal
+    if g.goal > 0.0 {
+        let yg = y(g.goal);
+        writeln!(
+            out,
+            r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#d00" stroke-dasharray="6,4"/><text x="{:.1}" y="{:.1}" fill="#d00">goal</text>"##,
+            x0,
+            yg,
+            x1,
+            yg,
+            x1 + 5.0,
+            yg + 4.0
+        )?;
+    }
+
+    // Curves and legend
+    let mut legend_y = MARGIN_T + 20.0;
+    let mut curves: Vec<(&str, &str, &[Sample])> = vec![(&g.points_name, "black", &points)];
+    for (ires, samples) in res.iter().enumerate() {
+        curves.push((&g.res_names[ires], COLORS[ires % COLORS.len()], samples));
+    }
+    for (name, color, samples) in curves {
+        let path: Vec<String> = samples
+            .iter()
+            .map(|&(t, v)| format!("{:.1},{:.1}", x(t), y(v)))
+            .collect();
+        writeln!(
+            out,
+            r#"<polyline fill="none" stroke="{}" stroke-width="1.5" points="{}"/>"#,
+            color,
+            path.join(" ")
+        )?;
+        writeln!(
+            out,
+            r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="2"/><text x="{:.1}" y="{:.1}">{}</text>"#,
+            x1 + 40.0,
+            legend_y,
+            x1 + 60.0,
+            legend_y,
+            color,
+            x1 + 65.0,
+            legend_y + 4.0,
+            escape(name)
+        )?;
+        legend_y += 16.0;
+    }
+    writeln!(out, "</svg>")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{opening, other_tower};
+
+    #[test]
+    fn test_write_svg() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        let seq = opening()[..3].to_vec();
+        let mut trace = Trace::new(&gs, &seq);
+        let steps: Vec<TraceStep> = trace.by_ref().collect();
+        assert_eq!(steps.len(), seq.len());
+        let mut end = GameState::new_from_game(&g);
+        end.copy_from(trace.state());
+        end.finish();
+
+        let mut out = Vec::new();
+        write_svg(&mut out, &gs, &steps, &end).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert_eq!(svg.matches("<polyline").count(), g.nres + 1); // and one for points
+        assert_eq!(svg.matches(">goal</text>").count(), 1);
+        assert_eq!(svg.matches(r#"stroke-dasharray="2,3""#).count(), seq.len());
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
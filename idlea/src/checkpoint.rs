@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::Saved;
+use crate::game::Move;
+use crate::say;
+
+const EVERY: Duration = Duration::from_secs(60); // how often "rand" saves a checkpoint
+
+/// Everything a `rand` or `imp` run needs to carry on where it stopped.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Checkpoint {
+    pub args: Vec<String>,       // the command line, which `--resume` runs again
+    pub seed: u64,               // rand: the run's --seed
+    pub next_restart: u64,       // rand: first restart not yet finished
+    pub done_restarts: Vec<u64>, // rand: restarts after that one which have finished
+    pub scores: Vec<f64>,        // rand: final score of every restart
+    pub plan: usize,             // imp: which of the starting plans is being improved
+    pub current: Vec<Move>,      // imp: that plan as far as it's improved
+    pub depth: usize,            // imp: depth of the next pass
+    pub archive: Vec<Saved>,     // best distinct plans so far, best first
+}
+
+impl Checkpoint {
+    pub fn load(file: &str) -> Result<Checkpoint, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(file)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Writes to a temporary file first, so a crash never leaves half a checkpoint.
+    pub fn save(&self, file: &str) -> Result<(), Box<dyn Error>> {
+        let temp_file = format!("{}.wtemp", file);
+        serde_json::to_writer(File::create(&temp_file)?, self)?;
+        std::fs::rename(&temp_file, file)?;
+        Ok(())
+    }
+}
+
+/// Where a run saves checkpoints, if anywhere, and what it resumes from.
+pub struct Checkpointing {
+    pub file: String,
+    pub args: Vec<String>,
+    pub resume: Option<Checkpoint>,
+    last_save: Instant,
+}
+
+impl Checkpointing {
+    pub fn new(file: &str, args: Vec<String>, resume: Option<Checkpoint>) -> Checkpointing {
+        Checkpointing {
+            file: file.to_string(),
+            args,
+            resume,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// Saves `ck` if there's a checkpoint file and it's time, or `now` anyway.
+    pub fn save(&mut self, ck: &mut Checkpoint, now: bool) {
+        if self.file.is_empty() || !(now || self.last_save.elapsed() >= EVERY) {
+            return;
+        }
+        ck.args = self.args.clone();
+        if let Err(err) = ck.save(&self.file) {
+            say!("Problem saving checkpoint {}: {}", self.file, err);
+        }
+        self.last_save = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::LvlUp;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut ck = Checkpoint::default();
+        for i in 0..5 {
+            ck.archive.push(Saved {
+                score: i as f64,
+                seed: Some(i as u64),
+                moves: vec![Move::LvlUp(LvlUp { uidx: i, level: 1 })],
+            });
+        }
+
+        let file = std::env::temp_dir().join("idlea_test_checkpoint.json");
+        let file = file.to_str().unwrap();
+        ck.seed = 42;
+        ck.save(file).unwrap();
+        let back = Checkpoint::load(file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(back.seed, 42);
+        assert_eq!(back.archive[3].moves, ck.archive[3].moves);
+    }
+}
@@ -0,0 +1,239 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use hashbrown::{HashMap, HashSet};
+use rand::Rng;
+
+use crate::game::Move;
+use crate::game_state::GameState;
+use crate::improve::{self, Switches};
+use crate::objective::Objective;
+use crate::schedule;
+use crate::upg_seq::{self, Operators};
+
+const ELITE: usize = 2; // best sequences carried over unchanged to the next generation
+const TOURNAMENT: usize = 3; // sequences compared when picking each parent
+const REPORT_EVERY: usize = 10; // generations between progress lines
+
+type Scored = (Vec<Move>, f64);
+
+/// Genetic algorithm: keeps a population of sequences, seeded from move files and topped up
+/// with `random_play`, and breeds each generation from the last with order crossover and
+/// `upg_seq::random_variation` mutations. Sequences are scored by worker threads.
+#[allow(clippy::too_many_arguments)]
+pub fn evolve_main<O: Objective + 'static>(
+    gs: GameState<'static>,
+    seed_files: &[String],
+    output_file: &str,
+    pop_size: usize,
+    gens: usize,
+    mutation: f64,
+    switches: &Switches,
+    ops: Operators,
+    lenient: bool,
+    obj: O,
+) -> Result<(), Box<dyn Error>> {
+    if pop_size <= ELITE {
+        return Err(format!("population must be bigger than {}", ELITE).into());
+    }
+    let g = gs.g;
+    let mut rng = rand::thread_rng();
+    let mut seqs = Vec::with_capacity(pop_size);
+    for file in seed_files {
+        seqs.push(upg_seq::load_sequence(file, &gs, lenient)?);
+    }
+    let mut scratchpad = GameState::new_from_game(g);
+    while seqs.len() < pop_size {
+        scratchpad.copy_from(&gs);
+        scratchpad.update_rates();
+        seqs.push(improve::random_play(&mut scratchpad, switches));
+    }
+    println!(
+        "Population of {}: {} from files, {} random",
+        seqs.len(),
+        seed_files.len().min(seqs.len()),
+        seqs.len().saturating_sub(seed_files.len())
+    );
+
+    let gs = Arc::new(gs);
+    let obj = Arc::new(obj);
+    let cpus = num_cpus::get();
+    println!("Scoring with {} threads", cpus);
+    let (mut tx_seq, rx_seq) = spmc::channel::<Vec<Move>>();
+    let (tx_scored, rx_scored) = mpsc::channel::<Scored>();
+    for _ in 0..cpus {
+        let rx_seq = rx_seq.clone();
+        let tx_scored = mpsc::Sender::clone(&tx_scored);
+        let gsc = Arc::clone(&gs);
+        let objc = Arc::clone(&obj);
+        thread::spawn(move || {
+            let gs: &GameState = gsc.as_ref();
+            let mut scratchpad = GameState::new_from_game(gs.g);
+            while let Ok(seq) = rx_seq.recv() {
+                let s = objc.score(gs, &mut scratchpad, &seq);
+                if tx_scored.send((seq, s)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let mut pop = evaluate(&mut tx_seq, &rx_scored, seqs);
+    let mut best_score = f64::NEG_INFINITY;
+    for gen in 0..=gens {
+        if gen > 0 {
+            let mut children = Vec::with_capacity(pop_size - ELITE);
+            while children.len() < pop_size - ELITE {
+                let a = &tournament(&pop, &mut rng).0;
+                let b = &tournament(&pop, &mut rng).0;
+                let mut child = crossover(a, b, &gs, &mut rng);
+                if rng.gen::<f64>() < mutation {
+                    if let Some(mutant) =
+                        upg_seq::random_variation(&child, &gs, false, ops, &mut rng)
+                    {
+                        child = mutant;
+                    }
+                }
+                children.push(child);
+            }
+            let mut next = evaluate(&mut tx_seq, &rx_scored, children);
+            next.extend(pop.drain(..ELITE));
+            sort_scored(&mut next);
+            pop = next;
+        }
+
+        if pop[0].1 > best_score {
+            best_score = pop[0].1;
+            println!(
+                "g{}: New best score {:.3} {}",
+                gen,
+                best_score,
+                obj.describe()
+            );
+            if !output_file.is_empty() {
+                let mut file = File::create(output_file)?;
+                for mv in &pop[0].0 {
+                    writeln!(&mut file, "{}", mv.to_string(g))?;
+                }
+            }
+        }
+        if gen % REPORT_EVERY == 0 {
+            println!(
+                "g{}: best {:.3} median {:.3} worst {:.3}",
+                gen,
+                pop[0].1,
+                pop[pop.len() / 2].1,
+                pop[pop.len() - 1].1
+            );
+        }
+    }
+    println!("Best score {:.3} {}", best_score, obj.describe());
+    schedule::print_sessions(&gs, &pop[0].0);
+    Ok(())
+}
+
+/// Has the worker threads score `seqs`, and returns them best first.
+fn evaluate(
+    tx_seq: &mut spmc::Sender<Vec<Move>>,
+    rx_scored: &mpsc::Receiver<Scored>,
+    seqs: Vec<Vec<Move>>,
+) -> Vec<Scored> {
+    let n = seqs.len();
+    for seq in seqs {
+        tx_seq.send(seq).unwrap();
+    }
+    let mut scored: Vec<Scored> = rx_scored.iter().take(n).collect();
+    sort_scored(&mut scored);
+    scored
+}
+
+fn sort_scored(scored: &mut [Scored]) {
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+}
+
+/// The best of a few sequences picked at random.
+fn tournament<'p, R: Rng>(pop: &'p [Scored], rng: &mut R) -> &'p Scored {
+    (0..TOURNAMENT)
+        .map(|_| rng.gen_range(0, pop.len()))
+        .min() // pop is sorted best first
+        .map(|i| &pop[i])
+        .unwrap()
+}
+
+/// Each move with how many times it came up before, so repeated production switches can be
+/// told apart.
+fn occurrences(seq: &[Move]) -> Vec<(Move, usize)> {
+    let mut seen = HashMap::new();
+    seq.iter()
+        .map(|mv| {
+            let n = seen.entry(*mv).or_insert(0);
+            *n += 1;
+            (*mv, *n - 1)
+        })
+        .collect()
+}
+
+/// Order crossover: keeps a random slice of `a` where it is and fills in the rest of `a`'s moves
+/// in the order `b` has them (moves `b` lacks keep their order from `a`), then repairs any
+/// prerequisites that ended up out of order. The child has the same moves as `a`.
+pub fn crossover<R: Rng>(a: &[Move], b: &[Move], gs: &GameState, rng: &mut R) -> Vec<Move> {
+    let n = a.len();
+    let (mut i, mut j) = (rng.gen_range(0, n + 1), rng.gen_range(0, n + 1));
+    if i > j {
+        std::mem::swap(&mut i, &mut j);
+    }
+    let keys_a = occurrences(a);
+    let keys_b = occurrences(b);
+    let kept: HashSet<(Move, usize)> = keys_a[i..j].iter().copied().collect();
+    let in_a: HashSet<(Move, usize)> = keys_a.iter().copied().collect();
+    let in_b: HashSet<(Move, usize)> = keys_b.iter().copied().collect();
+    let mut rest: Vec<Move> = keys_b
+        .iter()
+        .filter(|k| in_a.contains(*k) && !kept.contains(*k))
+        .map(|k| k.0)
+        .collect();
+    rest.extend(
+        keys_a
+            .iter()
+            .filter(|k| !in_b.contains(*k) && !kept.contains(*k))
+            .map(|k| k.0),
+    );
+
+    let mut child = Vec::with_capacity(n);
+    child.extend_from_slice(&rest[..i]);
+    child.extend_from_slice(&a[i..j]);
+    child.extend_from_slice(&rest[i..]);
+    upg_seq::repair(&child, gs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_crossover() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.update_rates();
+        let mut rng = rand::thread_rng();
+        let mut scratch = GameState::new_from_game(&g);
+        let sorted = |seq: &[Move]| {
+            let mut seq = seq.to_vec();
+            seq.sort_by_key(|mv| format!("{:?}", mv));
+            seq
+        };
+        for _ in 0..20 {
+            scratch.copy_from(&gs);
+            let a = improve::random_play(&mut scratch, &vec![]);
+            scratch.copy_from(&gs);
+            let b = improve::random_play(&mut scratch, &vec![]);
+            let child = crossover(&a, &b, &gs, &mut rng);
+            assert_eq!(sorted(&child), sorted(&a));
+            assert_eq!(upg_seq::repair(&child, &gs), child);
+        }
+    }
+}
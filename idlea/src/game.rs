@@ -1,6 +1,9 @@
 // use crate::game_state::GameState;
 //use std::collections::HashMap;
 use hashbrown::hash_map::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::schedule::Schedule;
 
 #[derive(Debug)]
 pub struct Game {
@@ -15,6 +18,18 @@ pub struct Game {
     pub upg_name_len: usize,
     pub goal: f64,
     pub prereqs: HashMap<Move, Vec<Move>>,
+    pub schedule: Option<Schedule>, // when the player can make moves, if not any time
+    pub offline: Option<Offline>,   // limits on earnings while the app is closed
+}
+
+/// How much accrues while the app is closed. A wait longer than `after` seconds, or one that
+/// runs into time the player is asleep, is spent offline: it earns at `rate` times the normal
+/// rates, for at most `max` seconds.
+#[derive(Debug)]
+pub struct Offline {
+    pub after: f64,
+    pub max: f64,
+    pub rate: f64,
 }
 
 #[derive(Debug)]
@@ -45,19 +60,19 @@ pub struct Boost {
     pub costs: Vec<Vec<i32>>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Move {
     LvlUp(LvlUp),
     Switch(Switch),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct LvlUp {
     pub uidx: usize,
     pub level: usize,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Switch {
     pub uidx: usize,
     pub iprod: usize, // 0 or 1
@@ -77,6 +92,8 @@ impl Game {
             upg_name_len: 0,
             goal: 0.,
             prereqs: HashMap::new(),
+            schedule: None,
+            offline: None,
         }
     }
 
@@ -221,6 +238,30 @@ impl Producer {
     }
 }
 
+impl Default for Offline {
+    /// No limits: offline time earns like online time.
+    fn default() -> Offline {
+        Offline {
+            after: f64::INFINITY,
+            max: f64::INFINITY,
+            rate: 1.0,
+        }
+    }
+}
+
+impl Offline {
+    /// `value` for the setting `key`, if it's more than 0 (or at least 0, with `zero_ok`).
+    pub fn check(key: &str, value: f64, zero_ok: bool) -> Result<f64, String> {
+        if value > 0.0 || (zero_ok && value == 0.0) {
+            Ok(value)
+        } else if zero_ok {
+            Err(format!("{} can't be negative, got {}", key, value))
+        } else {
+            Err(format!("{} must be more than 0, got {}", key, value))
+        }
+    }
+}
+
 impl Boost {
     pub fn new(name: String) -> Boost {
         Boost {
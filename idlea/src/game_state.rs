@@ -1,4 +1,5 @@
 use crate::game::{Game, Upgrade, Switch};
+use crate::say;
 
 #[derive(Debug)]
 pub struct GameState<'a> {
@@ -12,6 +13,8 @@ pub struct GameState<'a> {
     pub bonuses: Vec<i32>,
     pub gem_boost: i32,
     pub commercial_mod: f64,
+    pub checkins: usize, // number of check-ins so far, when the game has a schedule
+    pub last_checkin: f64, // time the current check-in started
     pub g: &'a Game,
 }
 
@@ -32,6 +35,8 @@ impl GameState<'_> {
             bonuses: vec![0; game.nres],
             gem_boost: 0,
             commercial_mod: -0.25,
+            checkins: 0,
+            last_checkin: f64::NEG_INFINITY,
             g: game,
         };
         for (iupg, upg) in game.upgrades.iter().enumerate() {
@@ -191,23 +196,117 @@ impl GameState<'_> {
         Some(max_t + self.g.overshoot)
     }
 
-    pub fn advance_time(&mut self, mut dt: f64) {
-        if self.time + dt > self.g.event_time {
-            dt = self.g.event_time - self.time;
-            self.time = self.g.event_time;
+    /// Seconds to wait before making a move that's affordable in `ttl` seconds: rounded up with
+    /// a second of padding, then pushed to when the player is next available. None if the wait
+    /// is spent offline and offline earnings can't cover it, or it runs past the event's end.
+    pub fn move_wait(&self, ttl: f64) -> Option<f64> {
+        let mut wait = self.available_after(ttl.ceil() + 1.0);
+        if let Some(off) = &self.g.offline {
+            if self.is_offline(wait) {
+                if ttl > off.rate * off.max {
+                    return None;
+                }
+                wait = self.available_after((ttl / off.rate).ceil() + 1.0);
+            }
+        }
+        if self.time + wait > self.g.event_time {
+            return None;
+        }
+        Some(wait)
+    }
+
+    /// Seconds to wait before levelling up `iup`, `delay` seconds after it's affordable, as
+    /// `move_wait`. None if it can't be done, including when a resource that production
+    /// switches use up runs short while the schedule keeps the player waiting.
+    pub fn lvlup_wait(&self, iup: usize, delay: f64) -> Option<f64> {
+        let wait = self.move_wait(self.time_till_lvlup(iup)? + delay)?;
+        let credit = self.credited(wait);
+        let cost = &self.g.upgrades[iup].costs()[self.levels[iup]];
+        let short = cost
+            .iter()
+            .zip(self.res_amt.iter().zip(self.res_rate.iter()))
+            .any(|(&c, (&have, &rate))| rate < 0.0 && have + rate * credit < c as f64);
+        if short {
+            None
         } else {
-            self.time += dt;
+            Some(wait)
         }
-        self.points += dt * self.pt_rate;
-        for (amt, rt) in self.res_amt.iter_mut().zip(self.res_rate.iter()) {
-            *amt += dt * *rt;
+    }
+
+    fn available_after(&self, t: f64) -> f64 {
+        match &self.g.schedule {
+            Some(sched) => sched.next_available(self.time + t) - self.time,
+            None => t,
         }
     }
 
+    /// Whether waiting `dt` seconds from now would be spent with the app closed.
+    pub fn is_offline(&self, dt: f64) -> bool {
+        match &self.g.offline {
+            Some(off) => {
+                dt > off.after
+                    || match &self.g.schedule {
+                        Some(sched) => sched.next_unavailable(self.time) < self.time + dt,
+                        None => false,
+                    }
+            }
+            None => false,
+        }
+    }
+
+    /// Seconds of normal earnings credited for waiting `dt` seconds from now.
+    pub fn credited(&self, dt: f64) -> f64 {
+        match &self.g.offline {
+            Some(off) if self.is_offline(dt) => off.rate * dt.min(off.max),
+            _ => dt,
+        }
+    }
+
+    /// Seconds into a wait of `dt` until `credit` seconds of normal earnings have accrued.
+    pub fn time_to_credit(&self, dt: f64, credit: f64) -> f64 {
+        match &self.g.offline {
+            Some(off) if self.is_offline(dt) => credit / off.rate,
+            _ => credit,
+        }
+    }
+
+    /// Seconds to wait before the player is available to make a production switch.
+    pub fn switch_wait(&self) -> f64 {
+        match &self.g.schedule {
+            Some(sched) => sched.next_available(self.time) - self.time,
+            None => 0.0,
+        }
+    }
+
+    fn note_checkin(&mut self) {
+        if let Some(sched) = &self.g.schedule {
+            if self.time - self.last_checkin > sched.session {
+                self.checkins += 1;
+                self.last_checkin = self.time;
+            }
+        }
+    }
+
+    pub fn advance_time(&mut self, dt: f64) {
+        let dt = dt.min(self.g.event_time - self.time);
+        self.earn(dt, self.credited(dt));
+    }
+
+    /// Plays on to the end of the event. That time earns at the full rate even with offline
+    /// earnings, as the player can open the app to collect without making a move, so a plan
+    /// gains nothing from a late move made just to reset the offline cap.
     pub fn finish(&mut self) {
         let t_remaining = self.g.event_time - self.time;
         if t_remaining > 0.0 {
-            self.advance_time(t_remaining);
+            self.earn(t_remaining, t_remaining);
+        }
+    }
+
+    fn earn(&mut self, dt: f64, credit: f64) {
+        self.time += dt;
+        self.points += credit * self.pt_rate;
+        for (amt, rt) in self.res_amt.iter_mut().zip(self.res_rate.iter()) {
+            *amt += credit * *rt;
         }
     }
 
@@ -229,9 +328,10 @@ impl GameState<'_> {
             }
         }
         if broke {
-            println!("Cost exceeds resources which are now {:?}", self.res_amt);
-            println!("cost was {:?}", self.g.upgrades[iupg].costs()[ilvl].iter());
+            say!("Cost exceeds resources which are now {:?}", self.res_amt);
+            say!("cost was {:?}", self.g.upgrades[iupg].costs()[ilvl].iter());
         }
+        self.note_checkin();
         self.update_rates();
     }
 
@@ -247,10 +347,74 @@ impl GameState<'_> {
         self.prod2.copy_from_slice(&src.prod2);
         self.gem_boost = src.gem_boost;
         self.commercial_mod = src.commercial_mod;
+        self.checkins = src.checkins;
+        self.last_checkin = src.last_checkin;
     }
 
     pub fn change_prod(&mut self, sw: &Switch) {
         self.prod2[sw.uidx] = sw.iprod != 0;
+        self.note_checkin();
         self.update_rates();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Offline;
+    use crate::schedule::Schedule;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_offline_cap() {
+        let mut g = other_tower();
+        g.offline = Some(Offline {
+            after: 600.0,
+            max: 3600.0,
+            rate: 0.5,
+        });
+        let mut gs = GameState::new_from_game(&g);
+        gs.update_rates();
+        assert_eq!(gs.credited(100.0), 100.0);
+        assert_eq!(gs.credited(1000.0), 500.0);
+        assert_eq!(gs.credited(10000.0), 1800.0);
+        assert_eq!(gs.move_wait(99.5), Some(101.0));
+        assert_eq!(gs.move_wait(1000.0), Some(2001.0));
+        assert_eq!(gs.move_wait(2000.0), None);
+
+        let rate = gs.pt_rate;
+        gs.advance_time(10000.0);
+        assert_eq!(gs.time, 10000.0);
+        assert_eq!(gs.points, 1800.0 * rate);
+
+        // No waiting past the end of the event, but the time after the last move is all earned.
+        gs.time = g.event_time - 1500.0;
+        assert_eq!(gs.move_wait(100.0), Some(101.0));
+        assert_eq!(gs.move_wait(1000.0), None);
+        gs.points = 0.0;
+        gs.finish();
+        assert_eq!(gs.time, g.event_time);
+        assert_eq!(gs.points, 1500.0 * rate);
+    }
+
+    #[test]
+    fn test_lvlup_wait_runs_short() {
+        let awake = other_tower();
+        let mut asleep = other_tower();
+        // Asleep from the start until 8:00.
+        asleep.schedule =
+            Some(Schedule::from_args("8-23", "2020-01-31T23:30:00Z", 10.0, 0.0).unwrap());
+        for (g, wait) in [(&awake, Some(1.0)), (&asleep, None)] {
+            let mut gs = GameState::new_from_game(g);
+            gs.update_rates();
+            let cost = &g.upgrades[0].costs()[gs.levels[0]];
+            let ires = cost.iter().position(|&c| c > 0).unwrap();
+            for (amt, &c) in gs.res_amt.iter_mut().zip(cost.iter()) {
+                *amt = c as f64;
+            }
+            gs.res_amt[ires] += 10.0;
+            gs.res_rate[ires] = -1.0; // as if a production switch were using it up
+            assert_eq!(gs.lvlup_wait(0, 0.0), wait);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::game::{Game, Move};
+use crate::game_state::GameState;
+use crate::schedule::group_checkins;
+use crate::upg_seq::{self, TimedMove};
+
+const REMINDER_MINUTES: i64 = 5; // length of each calendar event
+
+pub fn export_main(
+    gs: GameState,
+    moves_file: &str,
+    output_file: &str,
+    start: &str,
+    window_mins: f64,
+    lenient: bool,
+) -> Result<(), Box<dyn Error>> {
+    let start = DateTime::parse_from_rfc3339(start)
+        .map_err(|err| format!("bad --start time {:?}: {}", start, err))?
+        .with_timezone(&Utc);
+    let seq = upg_seq::load_sequence(moves_file, &gs, lenient)?;
+
+    let mut scratchpad = GameState::new_from_game(gs.g);
+    let timed = upg_seq::timeline(&gs, &mut scratchpad, &seq);
+    if timed.len() < seq.len() {
+        println!(
+            "Only the first {} of {} moves can be made, the rest are left out",
+            timed.len(),
+            seq.len()
+        );
+    }
+
+    let checkins = group_checkins(&timed, window_mins * 60.0);
+    println!(
+        "{} moves in {} check-ins, writing {}",
+        timed.len(),
+        checkins.len(),
+        output_file
+    );
+    let mut file = File::create(output_file)?;
+    write_ics(&mut file, gs.g, &checkins, start)?;
+    Ok(())
+}
+
+/// Writes one VEVENT per check-in. Each event starts when the last move of its check-in can be
+/// made, so everything listed is affordable by then.
+pub fn write_ics(
+    out: &mut dyn Write,
+    g: &Game,
+    checkins: &[&[TimedMove]],
+    start: DateTime<Utc>,
+) -> std::io::Result<()> {
+    let stamp = ics_time(Utc::now());
+    write_line(out, "BEGIN:VCALENDAR")?;
+    write_line(out, "VERSION:2.0")?;
+    write_line(out, "PRODID:-//idlea//plan export//EN")?;
+    for (ichk, moves) in checkins.iter().enumerate() {
+        let when = start + Duration::seconds(moves.last().unwrap().time.ceil() as i64);
+        let mut summary = format!("{}: {}", g.name, describe(g, &moves[0].mv));
+        if moves.len() > 1 {
+            summary += &format!(" (+{} more)", moves.len() - 1);
+        }
+        let details: Vec<String> = moves
+            .iter()
+            .map(|tm| {
+                let cost = cost_str(g, &tm.mv);
+                if cost.is_empty() {
+                    describe(g, &tm.mv)
+                } else {
+                    format!("{} for {}", describe(g, &tm.mv), cost)
+                }
+            })
+            .collect();
+        let details = details.join("\n");
+
+        write_line(out, "BEGIN:VEVENT")?;
+        write_line(
+            out,
+            &format!("UID:idlea-{}-{}@idlea", start.timestamp(), ichk),
+        )?;
+        write_line(out, &format!("DTSTAMP:{}", stamp))?;
+        write_line(out, &format!("DTSTART:{}", ics_time(when)))?;
+        write_line(
+            out,
+            &format!(
+                "DTEND:{}",
+                ics_time(when + Duration::minutes(REMINDER_MINUTES))
+            ),
+        )?;
+        write_line(out, &format!("SUMMARY:{}", escape(&summary)))?;
+        write_line(out, &format!("DESCRIPTION:{}", escape(&details)))?;
+        write_line(out, "BEGIN:VALARM")?;
+        write_line(out, "ACTION:DISPLAY")?;
+        write_line(out, "TRIGGER:PT0M")?;
+        write_line(out, &format!("DESCRIPTION:{}", escape(&summary)))?;
+        write_line(out, "END:VALARM")?;
+        write_line(out, "END:VEVENT")?;
+    }
+    write_line(out, "END:VCALENDAR")
+}
+
+fn describe(g: &Game, mv: &Move) -> String {
+    match mv {
+        Move::LvlUp(lvlup) => format!(
+            "{} to level {}",
+            g.upgrades[lvlup.uidx].get_name(),
+            lvlup.level
+        ),
+        Move::Switch(sw) => format!(
+            "switch {} to {}",
+            g.upgrades[sw.uidx].get_name(),
+            sw.to_string(g)
+        ),
+    }
+}
+
+fn cost_str(g: &Game, mv: &Move) -> String {
+    if let Move::LvlUp(lvlup) = mv {
+        let cost = &g.upgrades[lvlup.uidx].costs()[lvlup.level - 1];
+        let parts: Vec<String> = cost
+            .iter()
+            .zip(g.res_names.iter())
+            .filter(|(&amt, _)| amt > 0)
+            .map(|(amt, name)| format!("{} {}", amt, name))
+            .collect();
+        parts.join(", ")
+    } else {
+        String::new()
+    }
+}
+
+fn ics_time(t: DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Writes a content line, folded at 75 octets as RFC 5545 asks.
+fn write_line(out: &mut dyn Write, line: &str) -> std::io::Result<()> {
+    let mut rest = line;
+    let mut limit = 75;
+    while rest.len() > limit {
+        let mut split = limit;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        write!(out, "{}\r\n ", &rest[..split])?;
+        rest = &rest[split..];
+        limit = 74; // continuation lines start with a space
+    }
+    write!(out, "{}\r\n", rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::LvlUp;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_write_line_folds() {
+        let mut out = Vec::new();
+        write_line(&mut out, &"x".repeat(160)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.split("\r\n").collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|l| l.len() <= 75));
+    }
+
+    #[test]
+    fn test_write_ics_checkins() {
+        let g = other_tower();
+        let timed: Vec<TimedMove> = [(0, 2, 0.0), (0, 3, 300.0), (1, 1, 900.0), (0, 4, 5000.0)]
+            .iter()
+            .map(|&(uidx, level, time)| TimedMove {
+                time,
+                mv: Move::LvlUp(LvlUp { uidx, level }),
+            })
+            .collect();
+        let checkins = group_checkins(&timed, 10.0 * 60.0);
+        let sizes: Vec<usize> = checkins.iter().map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![2, 1, 1]);
+
+        let start = DateTime::parse_from_rfc3339("2020-01-31T18:00:00-07:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        let mut out = Vec::new();
+        write_ics(&mut out, &g, &checkins, start).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.matches("BEGIN:VEVENT").count(), 3);
+        let starts: Vec<&str> = text
+            .split("\r\n")
+            .filter(|l| l.starts_with("DTSTART:"))
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                "DTSTART:20200201T010500Z",
+                "DTSTART:20200201T011500Z",
+                "DTSTART:20200201T022320Z",
+            ]
+        );
+        assert!(text.contains("(+1 more)"));
+    }
+}
@@ -1,48 +1,191 @@
+use std::error::Error;
 use std::fs::File;
 use std::io::Write;
+use std::process;
 use std::thread;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{mpsc, Arc};
+use std::time::Duration;
 // use std::time::Instant;
-use cpu_time::ThreadTime;
-use std::collections::HashSet;
+use cpu_time::{ProcessTime, ThreadTime};
+use serde_json::json;
+use std::collections::{BTreeSet, HashSet};
 use spmc;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::game::{Upgrade, Move, LvlUp, Switch};
+use crate::cache::ScoreCache;
+use crate::archive::{Archive, Saved};
+use crate::checkpoint::{Checkpoint, Checkpointing};
+use crate::game::{Game, Move, LvlUp, Switch};
 use crate::game_state::GameState;
-use crate::upg_seq;
+use crate::objective::{Checkpoints, Objective};
+use crate::progress;
+use crate::say;
+use crate::schedule;
+use crate::upg_seq::{self, Operators};
 
 const DEPTH_THREADING: usize = 2; // use worker threads for this level of depth or higher
 const WT_A: f32 = 0.25; // weights are 2^-(ttl/WT_T) + WT_A
 const WT_T: f32 = 60.0 * 60.0; // 1 hour
 
 static mut PUSHY: bool = false;
+static mut OPERATORS: Operators = Operators::SLIDE_ONLY;
+static STOP: AtomicBool = AtomicBool::new(false);
 
-type Switches = Vec<u32>;
+pub type Switches = Vec<u32>;
 
 pub unsafe fn set_pushy(do_pushy: bool) {
     PUSHY = do_pushy;
 }
 
-pub fn improve_main(
+/// Sets the operators `imp` and `rand` try at depth 1.
+///
+/// # Safety
+/// Call before any optimizer threads start, as for `set_pushy`.
+pub unsafe fn set_operators(ops: Operators) {
+    OPERATORS = ops;
+}
+
+/// Makes `imp` and `rand` stop on Ctrl-C, or after `time_limit` seconds, keeping the best
+/// plan found so far. A second Ctrl-C quits at once.
+pub fn stop_on_signal(time_limit: Option<f64>) -> Result<(), Box<dyn Error>> {
+    ctrlc::set_handler(|| {
+        if STOP.swap(true, Ordering::Relaxed) {
+            process::exit(130);
+        }
+        say!("\nStopping after the current plans, Ctrl-C again to quit now");
+    })?;
+    if let Some(secs) = time_limit {
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs_f64(secs));
+            say!("\nTime limit reached, stopping");
+            STOP.store(true, Ordering::Relaxed);
+        });
+    }
+    Ok(())
+}
+
+fn stopping() -> bool {
+    STOP.load(Ordering::Relaxed)
+}
+
+/// The operators to try at `depth`. Each level of depth multiplies the cost of the levels
+/// above it, so deeper searches only slide moves.
+fn ops_for(depth: usize) -> Operators {
+    if depth == 1 {
+        unsafe { OPERATORS }
+    } else {
+        Operators::SLIDE_ONLY
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn improve_main<O: Objective + 'static>(
     gs: GameState<'static>, // initial game state
     initial_moves_file: &str,
     output_file: &str,
     max_depth: usize,
     fast: bool,
-) {
-    let seq = upg_seq::load_sequence(initial_moves_file, &gs);
+    lenient: bool,
+    archive: &Archive,
+    mut ckpt: Checkpointing,
+    obj: O,
+) -> Result<(), Box<dyn Error>> {
+    let mut ck = ckpt.resume.take().unwrap_or_default();
+    let starts = if archive.dir.is_empty() {
+        vec![upg_seq::load_sequence(initial_moves_file, &gs, lenient)?]
+    } else {
+        let plans = archive.load(&gs, lenient)?;
+        say!("Improving {} plans from {}", plans.len(), archive.dir);
+        plans
+    };
+    if !ck.current.is_empty() {
+        say!("Resuming from the checkpoint at depth {}", ck.depth);
+    }
 
+    let gs = Arc::new(gs);
+    let obj = Arc::new(obj);
+    let cache = Arc::new(ScoreCache::default());
+    for (iplan, start) in starts.iter().enumerate().skip(ck.plan) {
+        if starts.len() > 1 {
+            say!("Plan {} of {}", iplan + 1, starts.len());
+        }
+        let seq = if ck.current.is_empty() {
+            start.clone()
+        } else {
+            std::mem::take(&mut ck.current) // where the checkpoint left off
+        };
+        ck.plan = iplan;
+        improve_plan(
+            seq,
+            &gs,
+            output_file,
+            max_depth,
+            fast,
+            archive,
+            &mut ck,
+            &mut ckpt,
+            &obj,
+            &cache,
+        );
+        if stopping() {
+            break;
+        }
+        ck.plan = iplan + 1;
+        ck.current.clear();
+        ck.depth = 1;
+        ckpt.save(&mut ck, true);
+    }
+    if let Some(best) = ck.archive.first() {
+        if starts.len() > 1 {
+            say!("Best plan scores {:.3} {}", best.score, obj.describe());
+        }
+        schedule::print_sessions(&gs, &best.moves);
+    }
+    Ok(())
+}
+
+/// Improves `seq` until no pass up to `max_depth` finds anything better, or it's told to stop.
+#[allow(clippy::too_many_arguments)]
+fn improve_plan<O: Objective + 'static>(
+    seq: Vec<Move>,
+    gs: &Arc<GameState<'static>>,
+    output_file: &str,
+    max_depth: usize,
+    fast: bool,
+    archive: &Archive,
+    ck: &mut Checkpoint,
+    ckpt: &mut Checkpointing,
+    obj: &Arc<O>,
+    cache: &Arc<ScoreCache>,
+) {
     let mut scratchpad = GameState::new_from_game(gs.g);
 
-    println!("{} moves in initial sequence", seq.len());
-    let initial_points = upg_seq::score(&gs, &mut scratchpad, &seq);
-    let initial_score = upg_seq::score_spare(&gs, &mut scratchpad, &seq);
-    println!(
-        "Initial score is {:.4} with {:.3} hours to spare",
-        initial_points, initial_score
-    );
+    let jsonl = progress::jsonl();
+    let initial_points = upg_seq::score(gs, &mut scratchpad, &seq);
+    let initial_score = obj.score(gs, &mut scratchpad, &seq);
+    let bound = obj.upper_bound(gs);
+    if jsonl {
+        progress::event(json!({
+            "event": "start",
+            "cpu_secs": process_cpu_secs(),
+            "plan": ck.plan,
+            "score": initial_score,
+            "bound": bound,
+            "moves": seq.len(),
+        }));
+    } else {
+        println!("{} moves in initial sequence", seq.len());
+        println!(
+            "Initial score is {:.4} with {:.3} {}{}",
+            initial_points,
+            initial_score,
+            obj.describe(),
+            gap_note(initial_score, bound)
+        );
+    }
 
     // print_moves(&seq);
     // for mv in &seq {
@@ -57,32 +200,78 @@ pub fn improve_main(
 
     let mut best_score = initial_score;
     let mut best_seq = seq;
-    let mut depth = 1;
-    let gs = Arc::new(gs);
+    let mut depth = ck.depth.max(1);
+    archive_plan(&best_seq, best_score, archive, ck, output_file, gs.g);
     let cpus = num_cpus::get();
     let pushy = unsafe { PUSHY };
     loop {
-        print!("d{}: ", depth);
+        if !jsonl {
+            print!("d{}: ", depth);
+        }
         let (new_seq, new_score) = if depth >= DEPTH_THREADING {
-            println!("Optimizing with {} threads", cpus);
-            find_improvement_threaded(&best_seq, best_score, &gs, true, depth, cpus, fast, pushy)
+            if !jsonl {
+                println!("Optimizing with {} threads", cpus);
+            }
+            find_improvement_threaded(
+                &best_seq, best_score, gs, !jsonl, depth, cpus, fast, pushy, obj, cache,
+            )
         } else {
-            find_improvement(&best_seq, best_score, &gs, true, depth, fast, pushy)
+            find_improvement(
+                &best_seq,
+                best_score,
+                gs,
+                !jsonl,
+                depth,
+                fast,
+                pushy,
+                ops_for(depth),
+                &**obj,
+                cache,
+            )
         };
+        if jsonl {
+            progress::event(json!({
+                "event": "depth",
+                "cpu_secs": process_cpu_secs(),
+                "plan": ck.plan,
+                "depth": depth,
+                "score": best_score.max(new_score),
+                "improved": new_score > best_score,
+                "moves": new_seq.len(),
+                "cache_hit_pct": cache.hit_rate().0,
+            }));
+        }
+        if stopping() && new_score <= best_score {
+            say!("Stopped early");
+            break;
+        }
         if new_score > best_score {
-            println!(
-                "Found improvement by {:.3} to {:.3}",
-                new_score - best_score,
-                new_score
-            );
+            if jsonl {
+                progress::event(json!({
+                    "event": "new_best",
+                    "cpu_secs": process_cpu_secs(),
+                    "plan": ck.plan,
+                    "depth": depth,
+                    "initial": best_score,
+                    "score": new_score,
+                    "bound": bound,
+                    "moves": new_seq.len(),
+                }));
+            } else {
+                println!(
+                    "Found improvement by {:.3} to {:.3}{}",
+                    new_score - best_score,
+                    new_score,
+                    gap_note(new_score, bound)
+                );
+            }
             best_seq = new_seq;
             best_score = new_score;
             depth = 1;
-            if output_file != "" {
-                let mut file = File::create(output_file).unwrap();
-                for mv in &best_seq {
-                    writeln!(&mut file, "{}", mv.to_string(gs.g)).unwrap();
-                }
+            archive_plan(&best_seq, best_score, archive, ck, output_file, gs.g);
+            if stopping() {
+                say!("Stopped early");
+                break;
             }
         } else {
             depth += 1;
@@ -90,11 +279,54 @@ pub fn improve_main(
                 break;
             }
         }
+        ck.current = best_seq.clone();
+        ck.depth = depth;
+        ckpt.save(ck, true);
         // print!("d{}: ", depth);
         // let resp = find_improvement(best_seq, best_score, gs, true, depth);
         // new_seq = resp.0;
         // new_score = resp.1;
     }
+    ck.current = best_seq;
+    ck.depth = depth;
+    ckpt.save(ck, true);
+}
+
+/// Adds `seq` to the archive, and writes it out if it's the best plan so far.
+fn archive_plan(
+    seq: &[Move],
+    score: f64,
+    archive: &Archive,
+    ck: &mut Checkpoint,
+    output_file: &str,
+    g: &Game,
+) {
+    let best = ck.archive.first().map_or(true, |b| score > b.score);
+    if best && !output_file.is_empty() {
+        let mut file = File::create(output_file).unwrap();
+        for mv in seq {
+            writeln!(&mut file, "{}", mv.to_string(g)).unwrap();
+        }
+    }
+    let saved = Saved {
+        score,
+        seed: None,
+        moves: seq.to_vec(),
+    };
+    archive.add(&mut ck.archive, saved);
+}
+
+/// CPU seconds used by all threads so far, for JSON progress events.
+fn process_cpu_secs() -> f64 {
+    ProcessTime::now().as_duration().as_secs_f64()
+}
+
+/// How far `score` is from the best any plan could do, for progress lines.
+fn gap_note(score: f64, bound: Option<f64>) -> String {
+    match bound {
+        Some(b) => format!(" (bound {:.3}, gap {:.3})", b, b - score),
+        None => String::new(),
+    }
 }
 
 #[allow(dead_code)]
@@ -111,7 +343,8 @@ fn print_moves(moves: &[Move]) {
     println!();
 }
 
-fn find_improvement(
+#[allow(clippy::too_many_arguments)]
+fn find_improvement<O: Objective>(
     seq: &[Move],
     seq_score: f64,
     gs: &GameState,
@@ -119,29 +352,44 @@ fn find_improvement(
     depth: usize,
     fast: bool,
     pushy: bool,
+    ops: Operators,
+    obj: &O,
+    cache: &ScoreCache,
 ) -> (Vec<Move>, f64) {
     let mut best_score = seq_score;
     let mut scratchpad = GameState::new_from_game(gs.g);
     let mut best_seq = seq.to_vec();
 
-    let mut variations: Box<dyn upg_seq::VarIter> = if pushy {
-        Box::from(upg_seq::VariationsPushy::try_seqs(&seq, &gs.g))
-    } else {
-        Box::from(upg_seq::Variations::try_seqs(&seq, &gs.g))
-    };
+    let mut variations = upg_seq::variations(seq, gs, pushy, ops);
 
+    let checkpoints = Checkpoints::new(gs, seq, obj);
     while let Some(new_seq) = variations.next() {
-        let mut s = upg_seq::score_spare(gs, &mut scratchpad, new_seq);
+        if stopping() {
+            break;
+        }
+        let mut s = cache.score_with(gs, new_seq, || {
+            checkpoints.score(obj, &mut scratchpad, new_seq)
+        });
         if depth > 1 {
-            let (good_seq, good_score) =
-                find_improvement(new_seq, s, gs, false, depth - 1, fast, false);
+            let (good_seq, good_score) = find_improvement(
+                new_seq,
+                s,
+                gs,
+                false,
+                depth - 1,
+                fast,
+                false,
+                Operators::SLIDE_ONLY,
+                obj,
+                cache,
+            );
             s = good_score;
             if s > best_score {
                 best_seq = good_seq;
             }
         } else {
             if s > best_score {
-                best_seq.copy_from_slice(new_seq);
+                best_seq = new_seq.to_vec();
             }
         }
         let next_best = keep_score(s, best_score, seq_score, chatty);
@@ -153,7 +401,7 @@ fn find_improvement(
         }
     }
     if chatty {
-        println!();
+        println!(" {}", cache.describe());
     }
 
     (best_seq, best_score)
@@ -180,7 +428,8 @@ fn keep_score(sc: f64, best_score: f64, seq_score: f64, chatty: bool) -> f64 {
 }
 
 #[allow(dead_code)]
-fn find_improvement_threaded(
+#[allow(clippy::too_many_arguments)]
+fn find_improvement_threaded<O: Objective + 'static>(
     seq: &[Move],
     seq_score: f64,
     gs: &Arc<GameState<'static>>,
@@ -189,16 +438,14 @@ fn find_improvement_threaded(
     cpus: usize,
     fast: bool,
     pushy: bool,
+    obj: &Arc<O>,
+    cache: &Arc<ScoreCache>,
 ) -> (Vec<Move>, f64) {
     let mut best_score = seq_score;
     let mut scratchpad = GameState::new_from_game(gs.g);
     let mut best_seq = seq.to_vec();
 
-    let mut variations: Box<dyn upg_seq::VarIter> = if pushy {
-        Box::from(upg_seq::VariationsPushy::try_seqs(&seq, &gs.g))
-    } else {
-        Box::from(upg_seq::Variations::try_seqs(&seq, &gs.g))
-    };
+    let mut variations = upg_seq::variations(seq, gs, pushy, Operators::SLIDE_ONLY);
 
     let (mut tx_imp, rx_imp): (
         spmc::Sender<(Vec<Move>, f64)>,
@@ -211,6 +458,8 @@ fn find_improvement_threaded(
         let rx_imp = rx_imp.clone();
         let tx_best = mpsc::Sender::clone(&tx_best);
         let gsc = Arc::clone(&gs);
+        let objc = Arc::clone(obj);
+        let cachec = Arc::clone(cache);
         // let gs_thread: GameState<'static> = GameState::new_from_game(gs.g);
         // gs_thread.copy_from(gs);
         // let gsc = Arc::clone(&gsa);
@@ -218,14 +467,31 @@ fn find_improvement_threaded(
             let gs: &GameState = gsc.as_ref();
             // let (new_seq, sc) = rx_imp.recv().unwrap();
             while let Ok((new_seq, sc)) = rx_imp.recv() {
-                let rslt = find_improvement(&new_seq, sc, gs, false, depth - 1, fast, false);
+                let rslt = find_improvement(
+                    &new_seq,
+                    sc,
+                    gs,
+                    false,
+                    depth - 1,
+                    fast,
+                    false,
+                    Operators::SLIDE_ONLY,
+                    &*objc,
+                    &cachec,
+                );
                 tx_best.send(rslt).unwrap();
             }
         }));
     }
 
+    let checkpoints = Checkpoints::new(gs, seq, &**obj);
     while let Some(new_seq) = variations.next() {
-        let s = upg_seq::score_spare(gs, &mut scratchpad, new_seq);
+        if stopping() {
+            break;
+        }
+        let s = cache.score_with(gs, new_seq, || {
+            checkpoints.score(&**obj, &mut scratchpad, new_seq)
+        });
         let new_seq = new_seq.to_vec();
         tx_imp.send((new_seq, s)).unwrap();
     }
@@ -239,38 +505,93 @@ fn find_improvement_threaded(
         best_score = keep_score(s, best_score, seq_score, chatty);
     }
     if chatty {
-        println!();
+        println!(" {}", cache.describe());
     }
 
     (best_seq, best_score)
 }
 
-pub fn improve_main_random(
+#[allow(clippy::too_many_arguments)]
+pub fn improve_main_random<O: Objective + 'static>(
     gs: GameState<'static>,
     output_file: &str,
     max_depth: usize,
     fast: bool,
     switches: &Switches,
     depth_thr: f64,
+    seed: Option<u64>,
+    max_restarts: Option<u64>,
+    archive: &Archive,
+    mut ckpt: Checkpointing,
+    obj: O,
 ) {
-    let mut best_score = std::f64::MIN;
+    let resumed = ckpt.resume.is_some();
+    let mut ck = ckpt.resume.take().unwrap_or_default();
+    let mut best_score = ck.archive.first().map_or(f64::MIN, |saved| saved.score);
+    let mut best_seq = ck
+        .archive
+        .first()
+        .map_or_else(Vec::new, |saved| saved.moves.clone());
     let gs = Arc::new(gs);
+    let obj = Arc::new(obj);
+    let cache = Arc::new(ScoreCache::default());
     let cpus = num_cpus::get();
-    println!("Running in {} worker threads", cpus);
+    let bound = obj.upper_bound(&gs);
+    if let Some(b) = bound {
+        say!("No plan can score more than {:.3} {}", b, obj.describe());
+    }
+    let seed = if resumed {
+        say!(
+            "Resuming after {} restarts, best score {:.4}",
+            ck.scores.len(),
+            best_score
+        );
+        ck.seed
+    } else {
+        seed.unwrap_or_else(rand::random)
+    };
+    ck.seed = seed;
+    say!("Running in {} worker threads with --seed {}", cpus, seed);
+    if progress::jsonl() {
+        progress::event(json!({
+            "event": "start",
+            "cpu_secs": process_cpu_secs(),
+            "seed": seed,
+            "threads": cpus,
+            "restarts": ck.scores.len(),
+            "bound": bound,
+        }));
+    }
+    let restarts = Arc::new(AtomicU64::new(ck.next_restart));
+    let mut done_after: BTreeSet<u64> = ck.done_restarts.iter().cloned().collect();
+    let done_before = Arc::new(done_after.clone());
 
     let (tx, rx) = mpsc::channel();
-    let mut _handles = Vec::new();
+    let mut handles = Vec::new();
 
     for _ in 0..cpus {
         let txc = mpsc::Sender::clone(&tx);
         let gsc = Arc::clone(&gs);
+        let objc = Arc::clone(&obj);
+        let cachec = Arc::clone(&cache);
         let sw = switches.clone();
-        _handles.push(thread::spawn(move || {
+        let restartsc = Arc::clone(&restarts);
+        let donec = Arc::clone(&done_before);
+        handles.push(thread::spawn(move || {
             let start_time = ThreadTime::now();
             let gs: &GameState = gsc.as_ref();
             let mut scratchpad = GameState::new_from_game(gs.g);
-            loop {
-                let (seq, score) = improved_random(
+            let mut restarts_run = 0;
+            while !stopping() {
+                let restart = restartsc.fetch_add(1, Ordering::Relaxed);
+                if max_restarts.is_some_and(|max| restart >= max) {
+                    break;
+                }
+                if donec.contains(&restart) {
+                    continue; // finished before the checkpoint
+                }
+                let restart_seed = restart_seed(seed, restart);
+                let result = improved_random(
                     &gs,
                     &mut scratchpad,
                     max_depth,
@@ -278,16 +599,64 @@ pub fn improve_main_random(
                     &start_time,
                     &sw,
                     depth_thr,
+                    bound,
+                    restart_seed,
+                    &*objc,
+                    &cachec,
                 );
-                txc.send((seq, score)).unwrap();
+                if result.is_some() {
+                    restarts_run += 1;
+                }
+                txc.send((result, restart, restart_seed)).unwrap();
             }
+            (start_time.elapsed(), restarts_run)
         }));
     }
+    drop(tx);
 
-    for (seq, score) in rx {
+    let mut interrupted = 0;
+    for (result, restart, restart_seed) in rx {
+        record_restart(
+            &mut ck,
+            &mut done_after,
+            restart,
+            result.as_ref().map(|r| r.score),
+        );
+        let Improved {
+            seq,
+            score,
+            initial,
+            depth,
+        } = match result {
+            Some(result) => result,
+            None => {
+                interrupted += 1;
+                continue;
+            }
+        };
         if score > best_score {
-            println!("New best score! {:.4} hours left", score);
+            if progress::jsonl() {
+                progress::event(json!({
+                    "event": "new_best",
+                    "cpu_secs": process_cpu_secs(),
+                    "restart": restart,
+                    "seed": restart_seed,
+                    "depth": depth,
+                    "initial": initial,
+                    "score": score,
+                    "bound": bound,
+                    "moves": seq.len(),
+                }));
+            } else {
+                println!(
+                    "New best score! {:.4} hours left{} from random seed {}",
+                    score,
+                    gap_note(score, bound),
+                    restart_seed
+                );
+            }
             best_score = score;
+            best_seq = seq.clone();
             if output_file != "" {
                 // TODO: write to .temp file, rename
                 let temp_file = format!("{}.wtemp", output_file);
@@ -299,10 +668,150 @@ pub fn improve_main_random(
                 std::fs::rename(&temp_file, output_file).unwrap();
             }
         }
+        let saved = Saved {
+            score,
+            seed: Some(restart_seed),
+            moves: seq,
+        };
+        if archive.add(&mut ck.archive, saved) && !archive.dir.is_empty() {
+            if let Err(err) = archive.write(&ck.archive, gs.g) {
+                say!("Problem writing archive {}: {}", archive.dir, err);
+            }
+        }
+        ckpt.save(&mut ck, false);
+    }
+    ckpt.save(&mut ck, true);
+
+    let stats: Vec<(Duration, u64)> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    if progress::jsonl() {
+        for (thread, (cpu, restarts_run)) in stats.iter().enumerate() {
+            progress::event(json!({
+                "event": "thread",
+                "thread": thread,
+                "cpu_secs": cpu.as_secs_f64(),
+                "restarts": restarts_run,
+            }));
+        }
+        progress::event(json!({
+            "event": "summary",
+            "cpu_secs": process_cpu_secs(),
+            "restarts": ck.scores.len(),
+            "interrupted": interrupted,
+            "score": best_score,
+            "bound": bound,
+            "cache_hit_pct": cache.hit_rate().0,
+        }));
+        return;
+    }
+    let cpu_times: Vec<String> = stats
+        .iter()
+        .map(|(cpu, _)| format!("{:.1}", cpu.as_secs_f64()))
+        .collect();
+    println!(
+        "Ran {} restarts, best score {:.4}{}",
+        ck.scores.len(),
+        best_score,
+        gap_note(best_score, bound)
+    );
+    if interrupted > 0 {
+        println!(
+            "{} restart{} interrupted, not counted",
+            interrupted,
+            if interrupted == 1 { " was" } else { "s were" }
+        );
     }
+    if !output_file.is_empty() && !ck.scores.is_empty() {
+        println!("Best plan is in {}", output_file);
+    }
+    if !archive.dir.is_empty() {
+        println!(
+            "The {} best distinct plans are in {}",
+            ck.archive.len(),
+            archive.dir
+        );
+    }
+    if !ckpt.file.is_empty() {
+        println!("Checkpoint is in {}, continue with --resume", ckpt.file);
+    }
+    print_histogram(&ck.scores, 10);
+    println!("CPU seconds by thread: {}", cpu_times.join(", "));
+    schedule::print_sessions(&gs, &best_seq);
 }
 
-fn improved_random(
+/// Notes in `ck` how `restart` went: its final score, or None if it was stopped part way, in
+/// which case it isn't done and `--resume` runs it again. `done` holds the restarts after
+/// `ck.next_restart` that are done.
+fn record_restart(
+    ck: &mut Checkpoint,
+    done: &mut BTreeSet<u64>,
+    restart: u64,
+    score: Option<f64>,
+) {
+    let score = match score {
+        Some(score) => score,
+        None => return,
+    };
+    ck.scores.push(score);
+    done.insert(restart);
+    while done.remove(&ck.next_restart) {
+        ck.next_restart += 1;
+    }
+    ck.done_restarts = done.iter().cloned().collect();
+}
+
+/// The seed for the random plan of restart number `restart` in a run with `seed`, the same
+/// whichever thread gets to it.
+fn restart_seed(seed: u64, restart: u64) -> u64 {
+    StdRng::seed_from_u64(seed.wrapping_add(restart)).gen()
+}
+
+/// Prints how many `scores` fall in each of `bins` equal ranges.
+fn print_histogram(scores: &[f64], bins: usize) {
+    if scores.is_empty() {
+        return;
+    }
+    let lo = scores.iter().cloned().fold(f64::INFINITY, f64::min);
+    let hi = scores.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let width = (hi - lo) / bins as f64;
+    let mut counts = vec![0usize; bins];
+    for &s in scores {
+        let bin = if width > 0.0 {
+            (((s - lo) / width) as usize).min(bins - 1)
+        } else {
+            0
+        };
+        counts[bin] += 1;
+    }
+    let most = *counts.iter().max().unwrap();
+    println!("Scores:");
+    for (bin, &count) in counts.iter().enumerate() {
+        if width == 0.0 && bin > 0 {
+            break;
+        }
+        let line = format!(
+            "  {:>10.3} to {:>10.3} {:>6} {}",
+            lo + width * bin as f64,
+            lo + width * (bin + 1) as f64,
+            count,
+            "#".repeat((count * 40).div_ceil(most))
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+/// A finished restart of `rand`: the plan, its score, the score of the random plan it started
+/// from, and the deepest pass made.
+struct Improved {
+    seq: Vec<Move>,
+    score: f64,
+    initial: f64,
+    depth: usize,
+}
+
+/// Improves the random plan from `seed` as far as `max_depth` goes. None if the run is
+/// stopping before that's done, as the plan's score then says little.
+#[allow(clippy::too_many_arguments)]
+fn improved_random<O: Objective>(
     gs: &GameState<'static>,
     scratch: &mut GameState,
     max_depth: usize,
@@ -310,27 +819,46 @@ fn improved_random(
     start_time: &ThreadTime,
     sw: &Switches,
     depth_thr: f64,
-) -> (Vec<Move>, f64) {
+    bound: Option<f64>,
+    seed: u64,
+    obj: &O,
+    cache: &ScoreCache,
+) -> Option<Improved> {
     scratch.copy_from(gs);
     scratch.update_rates();
-    let mut best_seq = random_play(scratch, sw);
+    let mut best_seq = random_play_with(scratch, sw, &mut StdRng::seed_from_u64(seed));
     // println!("Random seq:");
     // for mv in &best_seq {
     //     println!(" {}", mv);
     // }
-    let initial_score = upg_seq::score_spare(&gs, scratch, &best_seq);
+    let initial_score = obj.score(gs, scratch, &best_seq);
     let mut best_score = initial_score;
     // println!("Random seq: {:.4} hours spare", initial_score);
     let mut depth = 1;
+    let mut deepest = 1;
     let pushy = unsafe { PUSHY };
     loop {
-        let (new_seq, new_score) =
-            find_improvement(&best_seq, best_score, &gs, false, depth, fast, pushy);
+        deepest = deepest.max(depth);
+        let (new_seq, new_score) = find_improvement(
+            &best_seq,
+            best_score,
+            gs,
+            false,
+            depth,
+            fast,
+            pushy,
+            ops_for(depth),
+            obj,
+            cache,
+        );
         if new_score > best_score {
             best_seq = new_seq;
             best_score = new_score;
             depth = 1;
         } else {
+            if stopping() {
+                return None; // not finished, so not a fair score
+            }
             if new_score < depth_thr {
                 break; // give up on lost causes early
             }
@@ -341,17 +869,44 @@ fn improved_random(
         }
     }
     let elapsed = start_time.elapsed().as_secs_f32();
-    println!(
-        "T={:.6} improved random: initial {:.4} final: {:.4} to spare",
-        elapsed, initial_score, best_score
-    );
-    (best_seq, best_score)
+    if progress::jsonl() {
+        progress::event(json!({
+            "event": "restart",
+            "cpu_secs": elapsed,
+            "seed": seed,
+            "initial": initial_score,
+            "score": best_score,
+            "depth": deepest,
+            "moves": best_seq.len(),
+            "cache_hit_pct": cache.hit_rate().0,
+        }));
+    } else {
+        println!(
+            "T={:.6} improved random (seed {}): initial {:.4} final: {:.4} to spare{}, {}",
+            elapsed,
+            seed,
+            initial_score,
+            best_score,
+            gap_note(best_score, bound),
+            cache.describe()
+        );
+    }
+    Some(Improved {
+        seq: best_seq,
+        score: best_score,
+        initial: initial_score,
+        depth: deepest,
+    })
+}
+
+pub fn random_play(gs: &mut GameState, sw: &Switches) -> Vec<Move> {
+    random_play_with(gs, sw, &mut rand::thread_rng())
 }
 
-fn random_play(gs: &mut GameState, sw: &Switches) -> Vec<Move> {
+/// `random_play` with the given random numbers, so a seeded `rng` plays the same game again.
+pub fn random_play_with<R: Rng>(gs: &mut GameState, sw: &Switches, rng: &mut R) -> Vec<Move> {
     let mut seq = Vec::new();
     let g = gs.g;
-    let mut rng = rand::thread_rng();
     let mut options = Vec::new();
     let mut weights = Vec::new();
     let mut scheduled = HashSet::new();
@@ -443,22 +998,9 @@ fn random_play(gs: &mut GameState, sw: &Switches) -> Vec<Move> {
     }
 
     for (uidx, &num_sw) in sw.iter().enumerate() {
-        if num_sw == 0 {
+        if num_sw == 0 || !upg_seq::switchable(g).contains(&uidx) {
             continue;
         }
-        if let Upgrade::Producer(up) = &g.upgrades[uidx] {
-            if up.produces2.len() == 0 {
-                panic!(
-                    "No production switches possible for upgrade {}: {}",
-                    uidx, up.name
-                );
-            }
-        } else {
-            panic!(
-                "No production switches possible for upgrade {}: non-producer",
-                uidx
-            );
-        }
         // let mut min_idx = seq.len();
         let mut min_idx = 0;
         for (iseq, &mv) in seq.iter().enumerate() {
@@ -488,12 +1030,68 @@ fn random_play(gs: &mut GameState, sw: &Switches) -> Vec<Move> {
     seq
 }
 
-pub fn switches_from_arg(swarg: &str) -> Switches {
+/// Parses `-s` as a number of production switches for each upgrade, in upgrade order. Only
+/// producers with a second production (or that can be paused) can be switched.
+pub fn switches_from_arg(swarg: &str, g: &Game) -> Result<Switches, String> {
     let mut sw = Switches::new();
-    for iupg in swarg.split(",") {
-        if iupg != "" {
-            sw.push(iupg.parse::<u32>().unwrap());
+    let switchable = upg_seq::switchable(g);
+    for iupg in swarg.split(',') {
+        if !iupg.is_empty() {
+            let num = iupg
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("bad number of switches {:?}", iupg))?;
+            let uidx = sw.len();
+            if num > 0 && !switchable.contains(&uidx) {
+                return Err(match g.upgrades.get(uidx) {
+                    Some(upg) => format!("{} has no production to switch to", upg.get_name()),
+                    None => format!(
+                        "switches given for {} upgrades, game has {}",
+                        uidx + 1,
+                        g.upgrades.len()
+                    ),
+                });
+            }
+            sw.push(num);
         }
     }
-    sw
+    Ok(sw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_seeded_random_play() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        gs.update_rates();
+        let mut scratch = GameState::new_from_game(&g);
+        let mut play = |seed| {
+            scratch.copy_from(&gs);
+            random_play_with(&mut scratch, &vec![2], &mut StdRng::seed_from_u64(seed))
+        };
+        let a = play(7);
+        assert_eq!(play(7), a);
+        assert_ne!(play(8), a);
+    }
+
+    #[test]
+    fn test_stopped_restart_not_done() {
+        let mut ck = Checkpoint::default();
+        let mut done = BTreeSet::new();
+        record_restart(&mut ck, &mut done, 0, Some(1.0));
+        record_restart(&mut ck, &mut done, 2, Some(3.0));
+        record_restart(&mut ck, &mut done, 1, None); // stopped part way
+        record_restart(&mut ck, &mut done, 3, Some(2.0));
+        assert_eq!(ck.next_restart, 1);
+        assert_eq!(ck.done_restarts, vec![2, 3]);
+        assert_eq!(ck.scores, vec![1.0, 3.0, 2.0]);
+        record_restart(&mut ck, &mut done, 1, Some(0.5)); // run again after --resume
+        assert_eq!(ck.next_restart, 4);
+        assert!(ck.done_restarts.is_empty());
+    }
 }
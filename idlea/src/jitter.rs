@@ -0,0 +1,213 @@
+use std::error::Error;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::game::Move;
+use crate::game_state::GameState;
+use crate::objective::Objective;
+use crate::upg_seq;
+
+// Every evaluation draws the same delays, so scores of different sequences compare fairly.
+const SEED: u64 = 20_200_131;
+
+/// How long after a move becomes affordable the player gets round to making it, in seconds.
+#[derive(Clone, Debug)]
+pub enum Delay {
+    Fixed(f64),
+    Uniform(f64, f64),
+    Exp(f64), // mean
+}
+
+impl Delay {
+    /// Parses `fixed:<min>`, `uniform:<min>-<max>` or `exp:<mean>`, all in minutes.
+    pub fn from_arg(arg: &str) -> Result<Delay, String> {
+        let bad = || {
+            format!(
+                "bad delay {:?}, use fixed:<min>, uniform:<min>-<max> or exp:<mean min>",
+                arg
+            )
+        };
+        let mins = |s: &str| -> Result<f64, String> {
+            match s.trim().parse::<f64>() {
+                Ok(m) if m >= 0.0 => Ok(m * 60.0),
+                _ => Err(bad()),
+            }
+        };
+        let mut parts = arg.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let value = parts.next().ok_or_else(bad)?;
+        match kind {
+            "fixed" => Ok(Delay::Fixed(mins(value)?)),
+            "exp" => Ok(Delay::Exp(mins(value)?)),
+            "uniform" => {
+                let mut ends = value.splitn(2, '-');
+                let lo = mins(ends.next().unwrap_or(""))?;
+                let hi = mins(ends.next().ok_or_else(bad)?)?;
+                if hi < lo {
+                    return Err(bad());
+                }
+                Ok(Delay::Uniform(lo, hi))
+            }
+            _ => Err(bad()),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        match *self {
+            Delay::Fixed(d) => d,
+            Delay::Uniform(lo, hi) => lo + (hi - lo) * rng.gen::<f64>(),
+            Delay::Exp(mean) => -mean * (1.0 - rng.gen::<f64>()).ln(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            Delay::Fixed(d) => format!("{:.1} min", d / 60.0),
+            Delay::Uniform(lo, hi) => format!("{:.1}-{:.1} min", lo / 60.0, hi / 60.0),
+            Delay::Exp(mean) => format!("exponential, mean {:.1} min", mean / 60.0),
+        }
+    }
+}
+
+/// Monte Carlo settings: how moves are delayed, and how many runs to score.
+#[derive(Clone, Debug)]
+pub struct Jitter {
+    pub delay: Delay,
+    pub samples: usize,
+}
+
+impl Jitter {
+    pub fn from_args(delay: &str, samples: usize) -> Result<Jitter, String> {
+        if samples == 0 {
+            return Err("need at least one sample".to_string());
+        }
+        Ok(Jitter {
+            delay: Delay::from_arg(delay)?,
+            samples,
+        })
+    }
+
+    /// `score_spare` of each of `samples` runs of `seq` with random delays, worst first.
+    pub fn spare_samples(
+        &self,
+        gs: &GameState,
+        scratch: &mut GameState,
+        seq: &[Move],
+    ) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let mut delays = vec![0.0; seq.len()];
+        let mut spares = Vec::with_capacity(self.samples);
+        for _ in 0..self.samples {
+            for d in delays.iter_mut() {
+                *d = self.delay.sample(&mut rng);
+            }
+            scratch.copy_from(gs);
+            scratch.update_rates();
+            spares.push(upg_seq::score_spare_delayed(
+                scratch, seq, gs.g.goal, &delays,
+            ));
+        }
+        spares.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        spares
+    }
+}
+
+/// The `pct`th percentile of scores sorted worst first, so the 0th is the worst.
+pub fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    let i = (pct / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[i]
+}
+
+/// A percentile of the spare time over jittered runs, for `--objective=p<pct>`.
+pub struct Percentile {
+    pub jitter: Jitter,
+    pub pct: f64,
+}
+
+impl Objective for Percentile {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        let mut start = GameState::new_from_game(scratch.g);
+        start.copy_from(scratch);
+        percentile(&self.jitter.spare_samples(&start, scratch, seq), self.pct)
+    }
+
+    // Delays are drawn from the first move on, so runs can't start part way through.
+    fn resumable(&self, _scratch: &GameState) -> bool {
+        false
+    }
+
+    fn charges_checkins(&self) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "hours to spare at p{} of {} jittered runs",
+            self.pct, self.jitter.samples
+        )
+    }
+}
+
+pub fn robust_main(
+    gs: GameState,
+    moves_file: &str,
+    jitter: &Jitter,
+    lenient: bool,
+) -> Result<(), Box<dyn Error>> {
+    let seq = upg_seq::load_sequence(moves_file, &gs, lenient)?;
+    let mut scratchpad = GameState::new_from_game(gs.g);
+    let nominal = upg_seq::score_spare(&gs, &mut scratchpad, &seq);
+    let spares = jitter.spare_samples(&gs, &mut scratchpad, &seq);
+    let mean = spares.iter().sum::<f64>() / spares.len() as f64;
+    let wins = spares.iter().filter(|&&s| s > 0.0).count();
+
+    println!("Nominal: {:.3} hours to spare", nominal);
+    println!(
+        "{} runs with moves delayed by {}:",
+        spares.len(),
+        jitter.delay.describe()
+    );
+    println!("  mean   {:>8.3}", mean);
+    println!("  worst  {:>8.3}", spares[0]);
+    for &pct in &[5.0, 10.0, 25.0, 50.0] {
+        println!("  p{:<5} {:>8.3}", pct, percentile(&spares, pct));
+    }
+    println!(
+        "  {:.1}% of runs reach the goal",
+        100.0 * wins as f64 / spares.len() as f64
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{opening, other_tower};
+
+    #[test]
+    fn test_jitter() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let mut scratch = GameState::new_from_game(&g);
+        let seq = opening();
+        let nominal = upg_seq::score_spare(&gs, &mut scratch, &seq);
+
+        let none = Jitter::from_args("fixed:0", 3).unwrap();
+        assert_eq!(
+            none.spare_samples(&gs, &mut scratch, &seq),
+            vec![nominal; 3]
+        );
+
+        let jitter = Jitter::from_args("exp:30", 20).unwrap();
+        let spares = jitter.spare_samples(&gs, &mut scratch, &seq);
+        assert!(spares.windows(2).all(|w| w[0] <= w[1]));
+        assert!(spares[spares.len() - 1] <= nominal);
+        assert_eq!(jitter.spare_samples(&gs, &mut scratch, &seq), spares);
+        assert_eq!(percentile(&spares, 0.0), spares[0]);
+        assert_eq!(percentile(&spares, 100.0), spares[19]);
+
+        assert!(Jitter::from_args("uniform:30-10", 10).is_err());
+        assert!(Jitter::from_args("gauss:10", 10).is_err());
+    }
+}
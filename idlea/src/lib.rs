@@ -1,17 +1,56 @@
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use crate::game::{Game, LvlUp, Move};
+    use crate::read_csv;
+
+    /// The Other Tower event from `g/`, which most tests play.
+    pub(crate) fn other_tower() -> Game {
+        read_csv::game_from_csv(concat!(env!("CARGO_MANIFEST_DIR"), "/../g/other_tower.csv"))
+            .unwrap()
+    }
+
+    /// Level ups of (upgrade, level) pairs.
+    pub(crate) fn lvlups(pairs: &[(usize, usize)]) -> Vec<Move> {
+        pairs
+            .iter()
+            .map(|&(uidx, level)| Move::LvlUp(LvlUp { uidx, level }))
+            .collect()
+    }
+
+    /// A short opening for `other_tower` that any of its tests can play.
+    pub(crate) fn opening() -> Vec<Move> {
+        lvlups(&[(0, 2), (0, 3), (1, 1), (0, 4), (1, 2)])
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
 }
 
+pub mod anneal;
+pub mod archive;
+pub mod beam;
+pub mod cache;
+pub mod chart;
+pub mod checkpoint;
+pub mod evolve;
 pub mod game;
 pub mod game_state;
+pub mod ics;
 pub mod improve;
+pub mod jitter;
+pub mod mcts;
+pub mod objective;
 pub mod play;
+pub mod progress;
 pub mod read_csv;
 pub mod read_yaml;
+pub mod relax;
+pub mod schedule;
+pub mod seq_diff;
+pub mod solve;
+pub mod trace;
 pub mod upg_seq;
 
 // pub use crate::game;
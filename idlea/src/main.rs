@@ -4,10 +4,28 @@ use serde::Deserialize;
 
 use idlea::game_state::GameState;
 use idlea::game::Game;
+use idlea::anneal::{self, Cooling};
+use idlea::archive::Archive;
+use idlea::beam;
+use idlea::chart;
+use idlea::checkpoint::{Checkpoint, Checkpointing};
+use idlea::evolve;
 use idlea::play::play;
 use idlea::read_csv;
 use idlea::improve;
+use idlea::jitter::{self, Jitter};
+use idlea::mcts;
+use idlea::objective;
+use idlea::progress;
+use idlea::ics;
 use idlea::read_yaml;
+use idlea::relax;
+use idlea::say;
+use idlea::schedule::Schedule;
+use idlea::seq_diff;
+use idlea::solve;
+use idlea::trace;
+use idlea::upg_seq::Operators;
 
 const USAGE: &'static str = "
 Idle Apoc Event helper
@@ -15,7 +33,20 @@ Idle Apoc Event helper
 Usage:
   idlea play [options]
   idlea imp [options] <initial>
+  idlea imp [options] --resume=<file>
+  idlea imp [options] --archive=<dir>
   idlea rand [options]
+  idlea export-ics [options] <initial>
+  idlea diff [options] <initial> <other>
+  idlea trace [options] <initial>
+  idlea chart [options] <initial>
+  idlea robust [options] <initial>
+  idlea anneal [options] <initial>
+  idlea evolve [options] [<seeds>...]
+  idlea beam [options]
+  idlea mcts [options]
+  idlea solve [options]
+  idlea upper-bound [options]
   idlea (-h | --help)
 
 Options:
@@ -26,9 +57,50 @@ Options:
   -o --output=<file>   output data file.
   -d --depth=<num>     looping depth for improvements [default: 1].
   -f --fast            Pick first optimization, not the best.
-  -s --switches=<str>  Use N0,N1,N2,... production switches for optimization.
+  -s --switches=<str>  Start random plans with N0,N1,N2,... production switches; the
+                       optimizer adds and removes switches itself anyway.
   -p --pushy           Whether or not to try pushy variations.
+  --ops=<list>         Variations to try: slide, switches, swap, block, reverse and
+                       trailing (see README). [default: slide,switches]
   --dthr=<num>         Depth threshold -- don't go deep below this score. [default: -10.0]
+  --seed=<num>         Seed for rand's random plans, to repeat a run (random if not given).
+  --time-limit=<secs>  Stop imp or rand after this many seconds, keeping the best plan.
+  --max-restarts=<num>  Stop rand after this many random plans.
+  --checkpoint=<file>  Save imp or rand progress to this file as it goes.
+  --resume=<file>      Carry on from a checkpoint, with the options it was started with.
+  --archive=<dir>      rand keeps its best distinct plans here; imp improves them all.
+  --archive-size=<num>  Plans to keep in the archive. [default: 10]
+  --min-distance=<num>  How many places moves must move on average for plans to count
+                       as distinct. [default: 1.0]
+  --log-format=<fmt>   Progress from imp and rand as text, or jsonl for one JSON object
+                       per event. [default: text]
+  --lenient            Skip moves that can't be loaded instead of stopping.
+  --start=<time>       Real event start time, RFC 3339 (e.g. 2020-01-31T18:00:00-07:00).
+  --window=<min>       Merge moves this close together into one check-in. [default: 10]
+  --format=<fmt>       Trace output format, csv or json. [default: csv]
+  --awake=<hours>      Only make moves in these daily local hours, e.g. 7:30-23 or 8-12,13-23.
+                       Needs --start; --window sets how long one check-in lasts.
+  --checkin-penalty=<hours>  Hours of spare time to charge per check-in. [default: 0]
+  --event-hours=<h>    How long the event lasts, instead of the game file's length.
+  --horizon=<hours>    Maximise points this many hours on from the start (or -c state),
+                       instead of --objective.
+  --objective=<obj>    What imp and rand maximise: spare, points, points@<hours>,
+                       milestone:<points>, res:<name> or p<pct> (a percentile of
+                       spare time over --samples runs with --jitter). [default: spare]
+  --jitter=<delay>     Random delay before each move: fixed:<min>, uniform:<min>-<max>
+                       or exp:<mean min>. [default: exp:10]
+  --samples=<num>      Number of jittered runs to score. [default: 200]
+  --iters=<num>        Iterations for anneal. [default: 20000]
+  --temp=<from:to>     Anneal temperatures, in score units. [default: 0.01:0.0001]
+  --cooling=<kind>     How anneal cools, geometric or linear. [default: geometric]
+  --pop=<num>          Population size for evolve. [default: 40]
+  --gens=<num>         Generations for evolve. [default: 200]
+  --mutation=<p>       Chance that evolve mutates a new sequence. [default: 0.5]
+  --width=<num>        Partial plans beam keeps at each step. [default: 50]
+  --playouts=<num>     MCTS playouts per move. [default: 2000]
+  --next               Only suggest the next move (mcts).
+  --moves=<num>        Level ups solve plans, from the --config state. [default: 8]
+  --budget=<secs>      Seconds solve searches before giving up. [default: 60]
 ";
 
 // TODO: make some options specific to commands, more subcommand help
@@ -43,19 +115,101 @@ struct Args {
     flag_fast: bool,
     flag_switches: String,
     flag_pushy: bool,
+    flag_ops: String,
     flag_dthr: f64,
+    flag_seed: Option<u64>,
+    flag_time_limit: Option<f64>,
+    flag_max_restarts: Option<u64>,
+    flag_checkpoint: String,
+    flag_resume: String,
+    flag_archive: String,
+    flag_archive_size: usize,
+    flag_min_distance: f64,
+    flag_log_format: String,
+    flag_lenient: bool,
+    flag_start: String,
+    flag_window: f64,
+    flag_format: String,
+    flag_objective: String,
+    flag_event_hours: Option<f64>,
+    flag_horizon: Option<f64>,
+    flag_awake: String,
+    flag_checkin_penalty: f64,
+    flag_jitter: String,
+    flag_samples: usize,
+    flag_iters: usize,
+    flag_temp: String,
+    flag_cooling: String,
+    flag_pop: usize,
+    flag_gens: usize,
+    flag_mutation: f64,
+    flag_width: usize,
+    flag_playouts: usize,
+    flag_next: bool,
+    flag_moves: usize,
+    flag_budget: f64,
     arg_initial: String,
+    arg_other: String,
+    arg_seeds: Vec<String>,
     cmd_play: bool,
     cmd_imp: bool,
     cmd_rand: bool,
+    cmd_export_ics: bool,
+    cmd_diff: bool,
+    cmd_trace: bool,
+    cmd_chart: bool,
+    cmd_robust: bool,
+    cmd_anneal: bool,
+    cmd_evolve: bool,
+    cmd_beam: bool,
+    cmd_mcts: bool,
+    cmd_solve: bool,
+    cmd_upper_bound: bool,
 }
 
 static mut GAME: Option<Game> = None;
 
+fn parse_args(argv: &[String]) -> Args {
+    Docopt::new(USAGE)
+        .and_then(|d| d.argv(argv).deserialize())
+        .unwrap_or_else(|e| e.exit())
+}
+
 fn main() {
-    let args: Args = Docopt::new(USAGE)
-        .and_then(|d| d.deserialize())
-        .unwrap_or_else(|e| e.exit());
+    let mut argv: Vec<String> = std::env::args().collect();
+    let mut args = parse_args(&argv);
+    let resume = if !args.flag_resume.is_empty() {
+        let ck = Checkpoint::load(&args.flag_resume).unwrap_or_else(|err| {
+            println!("Problem reading checkpoint: {}", err);
+            process::exit(1);
+        });
+        argv = ck.args.clone();
+        let resume_file = args.flag_resume;
+        args = parse_args(&argv);
+        if args.flag_checkpoint.is_empty() {
+            args.flag_checkpoint = resume_file;
+        }
+        Some(ck)
+    } else {
+        None
+    };
+    progress::set_format(&args.flag_log_format).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    });
+    if let Some(ck) = &resume {
+        say!("Resuming: {}", ck.args.join(" "));
+    }
+    let ckpt = Checkpointing::new(&args.flag_checkpoint, argv, resume);
+    if args.flag_archive_size == 0 {
+        println!("--archive-size must be at least 1");
+        process::exit(1);
+    }
+    let archive = Archive {
+        dir: args.flag_archive.clone(),
+        size: args.flag_archive_size,
+        min_distance: args.flag_min_distance,
+    };
 
     let config = if &args.flag_config != "" {
         let config = read_yaml::load_config_yaml(&args.flag_config).unwrap_or_else(|err| {
@@ -74,11 +228,41 @@ fn main() {
         &args.flag_game
     };
 
+    let mut game = read_csv::game_from_csv(game_csv).unwrap_or_else(|err| {
+        println!("Problem with game {}: {}", game_csv, err);
+        process::exit(1);
+    });
+    if let Some(config) = &config {
+        config.fix_game(&mut game).unwrap_or_else(|err| {
+            println!("Problem with config: {}", err);
+            process::exit(1);
+        });
+    }
+    if let Some(hours) = args.flag_event_hours {
+        if hours <= 0.0 {
+            println!("Event must last more than {} hours", hours);
+            process::exit(1);
+        }
+        game.event_time = hours * 60.0 * 60.0;
+    }
+    if !args.flag_awake.is_empty() {
+        let sched = Schedule::from_args(
+            &args.flag_awake,
+            &args.flag_start,
+            args.flag_window,
+            args.flag_checkin_penalty,
+        )
+        .unwrap_or_else(|err| {
+            println!("Problem with schedule: {}", err);
+            process::exit(1);
+        });
+        game.schedule = Some(sched);
+    }
     let g = unsafe {
-        GAME = Some(read_csv::game_from_csv(game_csv).unwrap());
+        GAME = Some(game);
         GAME.as_ref().unwrap()
     };
-    println!("Game {}: {}", game_csv, g.name);
+    say!("Game {}: {}", game_csv, g.name);
 
     // println!("{:#?}", g);
     let mut gs = GameState::new_from_game(g);
@@ -88,11 +272,56 @@ fn main() {
     } else {
         gs.gem_boost = args.flag_boost;
     }
-    println!("Boost = {}", gs.gem_boost);
+    say!("Boost = {}", gs.gem_boost);
     // println!("{:#?}", gs);
 
+    let ops = Operators::from_arg(&args.flag_ops).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    });
     unsafe {
         improve::set_pushy(args.flag_pushy);
+        improve::set_operators(ops);
+    }
+
+    let jitter = Jitter::from_args(&args.flag_jitter, args.flag_samples).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    });
+    let obj = match args.flag_horizon {
+        Some(_) if args.flag_objective != "spare" => {
+            Err("give --horizon or --objective, not both".to_string())
+        }
+        Some(hours) => objective::horizon(hours, &gs),
+        None => objective::from_arg(&args.flag_objective, g, &jitter),
+    }
+    .unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    });
+    if args.flag_checkin_penalty != 0.0 {
+        let problem = if args.flag_awake.is_empty() {
+            "--checkin-penalty needs --awake"
+        } else if !obj.charges_checkins() {
+            "--checkin-penalty only counts with --objective spare, milestone:<points> or p<percentile>"
+        } else {
+            ""
+        };
+        if !problem.is_empty() {
+            println!("{}", problem);
+            process::exit(1);
+        }
+    }
+    let switches = improve::switches_from_arg(&args.flag_switches, g).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    });
+
+    if args.cmd_imp || args.cmd_rand {
+        improve::stop_on_signal(args.flag_time_limit).unwrap_or_else(|err| {
+            println!("Problem handling Ctrl-C: {}", err);
+            process::exit(1);
+        });
     }
 
     if args.cmd_play {
@@ -104,9 +333,16 @@ fn main() {
             &args.flag_output,
             args.flag_depth,
             args.flag_fast,
-        );
+            args.flag_lenient,
+            &archive,
+            ckpt,
+            obj,
+        )
+        .unwrap_or_else(|err| {
+            println!("Problem reading moves: {}", err);
+            process::exit(1);
+        });
     } else if args.cmd_rand {
-        let switches = improve::switches_from_arg(&args.flag_switches);
         improve::improve_main_random(
             gs,
             &args.flag_output,
@@ -114,6 +350,124 @@ fn main() {
             args.flag_fast,
             &switches,
             args.flag_dthr,
+            args.flag_seed,
+            args.flag_max_restarts,
+            &archive,
+            ckpt,
+            obj,
+        );
+    } else if args.cmd_export_ics {
+        let output = if !args.flag_output.is_empty() {
+            args.flag_output.clone()
+        } else {
+            format!("{}.ics", args.arg_initial)
+        };
+        ics::export_main(
+            gs,
+            &args.arg_initial,
+            &output,
+            &args.flag_start,
+            args.flag_window,
+            args.flag_lenient,
+        )
+        .unwrap_or_else(|err| {
+            println!("Problem exporting plan: {}", err);
+            process::exit(1);
+        });
+    } else if args.cmd_diff {
+        seq_diff::diff_main(gs, &args.arg_initial, &args.arg_other, args.flag_lenient)
+            .unwrap_or_else(|err| {
+                println!("Problem reading moves: {}", err);
+                process::exit(1);
+            });
+    } else if args.cmd_trace {
+        trace::trace_main(
+            gs,
+            &args.arg_initial,
+            &args.flag_output,
+            &args.flag_format,
+            args.flag_lenient,
+        )
+        .unwrap_or_else(|err| {
+            println!("Problem tracing moves: {}", err);
+            process::exit(1);
+        });
+    } else if args.cmd_chart {
+        let output = if !args.flag_output.is_empty() {
+            args.flag_output.clone()
+        } else {
+            format!("{}.svg", args.arg_initial)
+        };
+        chart::chart_main(gs, &args.arg_initial, &output, args.flag_lenient).unwrap_or_else(
+            |err| {
+                println!("Problem charting moves: {}", err);
+                process::exit(1);
+            },
+        );
+    } else if args.cmd_robust {
+        jitter::robust_main(gs, &args.arg_initial, &jitter, args.flag_lenient).unwrap_or_else(
+            |err| {
+                println!("Problem reading moves: {}", err);
+                process::exit(1);
+            },
+        );
+    } else if args.cmd_anneal {
+        let cooling =
+            Cooling::from_args(&args.flag_temp, &args.flag_cooling).unwrap_or_else(|err| {
+                println!("{}", err);
+                process::exit(1);
+            });
+        anneal::anneal_main(
+            gs,
+            &args.arg_initial,
+            &args.flag_output,
+            args.flag_iters,
+            &cooling,
+            args.flag_pushy,
+            ops,
+            args.flag_lenient,
+            obj,
+        )
+        .unwrap_or_else(|err| {
+            println!("Problem reading moves: {}", err);
+            process::exit(1);
+        });
+    } else if args.cmd_evolve {
+        evolve::evolve_main(
+            gs,
+            &args.arg_seeds,
+            &args.flag_output,
+            args.flag_pop,
+            args.flag_gens,
+            args.flag_mutation,
+            &switches,
+            ops,
+            args.flag_lenient,
+            obj,
+        )
+        .unwrap_or_else(|err| {
+            println!("Problem evolving moves: {}", err);
+            process::exit(1);
+        });
+    } else if args.cmd_beam {
+        beam::beam_main(gs, &args.flag_output, args.flag_width, obj).unwrap_or_else(|err| {
+            println!("Problem building plan: {}", err);
+            process::exit(1);
+        });
+    } else if args.cmd_mcts {
+        mcts::mcts_main(gs, &args.flag_output, args.flag_playouts, args.flag_next).unwrap_or_else(
+            |err| {
+                println!("Problem building plan: {}", err);
+                process::exit(1);
+            },
         );
+    } else if args.cmd_solve {
+        solve::solve_main(gs, &args.flag_output, args.flag_moves, args.flag_budget)
+            .unwrap_or_else(|err| {
+                println!("Problem solving: {}", err);
+                process::exit(1);
+            });
+    } else if args.cmd_upper_bound {
+        relax::upper_bound_main(gs);
     }
 }
@@ -0,0 +1,358 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use rand::Rng;
+
+use crate::beam;
+use crate::game::{Game, LvlUp, Move};
+use crate::game_state::GameState;
+use crate::improve;
+use crate::objective::Spare;
+use crate::schedule;
+use crate::upg_seq;
+
+const EXPLORE: f64 = 0.7; // UCT exploration constant, for scores scaled to 0..1
+
+struct Node<'a> {
+    gs: GameState<'a>,
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    total: f64,      // sum of playout values, see `Mcts::value`
+    best_score: f64, // best hours to spare of any playout through here
+}
+
+/// A Monte Carlo search tree over the level ups possible from a game state. Each playout picks
+/// a path down the tree by UCT, adds a node, finishes the plan with `random_play` and scores
+/// the whole plan with `score_spare`.
+pub struct Mcts<'a> {
+    start: GameState<'a>,
+    prefix: Vec<Move>,
+    nodes: Vec<Node<'a>>,
+    lo: f64, // range of values seen, to scale them to 0..1
+    hi: f64,
+    greedy: f64, // hours to spare of the root's greedy plan, which values are measured from
+    pub best: Option<(Vec<Move>, f64)>, // best complete plan seen, starting with the greedy one
+}
+
+impl<'a> Mcts<'a> {
+    /// Runs `playouts` playouts from the state reached by making `prefix` from `start`.
+    pub fn search<R: Rng>(
+        start: &GameState<'a>,
+        prefix: &[Move],
+        playouts: usize,
+        rng: &mut R,
+    ) -> Mcts<'a> {
+        let g = start.g;
+        let mut first = GameState::new_from_game(g);
+        first.copy_from(start);
+        first.update_rates();
+        let mut root = GameState::new_from_game(g);
+        root.copy_from(&first);
+        for mv in prefix {
+            upg_seq::play_move(&mut root, mv);
+        }
+        let mut scratchpad = GameState::new_from_game(g);
+        let mut greedy_seq = prefix.to_vec();
+        greedy_seq.extend(beam::beam(&root, 1, &Spare).seq);
+        let greedy = upg_seq::score_spare(&first, &mut scratchpad, &greedy_seq);
+        let mut tree = Mcts {
+            start: first,
+            prefix: prefix.to_vec(),
+            nodes: Vec::new(),
+            lo: f64::INFINITY,
+            hi: f64::NEG_INFINITY,
+            greedy,
+            best: Some((greedy_seq, greedy)),
+        };
+        tree.add_node(root, None, None);
+
+        for _ in 0..playouts {
+            let mut idx = 0;
+            while tree.nodes[idx].untried.is_empty() && !tree.nodes[idx].children.is_empty() {
+                idx = tree.select(idx);
+            }
+            idx = tree.expand(idx, rng);
+            let s = tree.rollout(idx, &mut scratchpad);
+            let value = tree.value(s);
+            tree.lo = tree.lo.min(value);
+            tree.hi = tree.hi.max(value);
+            let mut at = Some(idx);
+            while let Some(i) = at {
+                let node = &mut tree.nodes[i];
+                node.visits += 1;
+                node.total += value;
+                node.best_score = node.best_score.max(s);
+                at = node.parent;
+            }
+        }
+        tree
+    }
+
+    /// What a playout scoring `s` hours to spare is worth: the signed log of how many hours
+    /// it beats the greedy plan by. Random playouts can be thousands of hours short, and
+    /// without the log a few of those would swamp the means that UCT compares.
+    fn value(&self, s: f64) -> f64 {
+        let ahead = s - self.greedy;
+        ahead.signum() * ahead.abs().ln_1p()
+    }
+
+    /// The root's children as (move, visits, best hours to spare of any playout through
+    /// it), most visited first.
+    pub fn next_moves(&self) -> Vec<(Move, u32, f64)> {
+        let mut stats: Vec<(Move, u32, f64)> = self.nodes[0]
+            .children
+            .iter()
+            .map(|&c| {
+                let node = &self.nodes[c];
+                (node.mv.unwrap(), node.visits, node.best_score)
+            })
+            .collect();
+        stats.sort_by_key(|st| std::cmp::Reverse(st.1));
+        stats
+    }
+
+    fn add_node(&mut self, gs: GameState<'a>, mv: Option<Move>, parent: Option<usize>) -> usize {
+        let mut untried = Vec::new();
+        for (uidx, upg) in gs.g.upgrades.iter().enumerate() {
+            let lvlup = Move::LvlUp(LvlUp {
+                uidx,
+                level: gs.levels[uidx] + 1,
+            });
+            if gs.levels[uidx] < upg.costs().len()
+                && upg_seq::ready(&gs, &lvlup)
+                && gs.time_till_lvlup(uidx).is_some()
+            {
+                untried.push(lvlup);
+            }
+        }
+        self.nodes.push(Node {
+            gs,
+            mv,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            total: 0.0,
+            best_score: f64::NEG_INFINITY,
+        });
+        let idx = self.nodes.len() - 1;
+        if let Some(p) = parent {
+            self.nodes[p].children.push(idx);
+        }
+        idx
+    }
+
+    fn select(&self, idx: usize) -> usize {
+        let node = &self.nodes[idx];
+        let range = if self.hi > self.lo {
+            self.hi - self.lo
+        } else {
+            1.0
+        };
+        let uct = |c: usize| {
+            let child = &self.nodes[c];
+            let mean = (child.total / child.visits as f64 - self.lo) / range;
+            mean + EXPLORE * ((node.visits as f64).ln() / child.visits as f64).sqrt()
+        };
+        *node
+            .children
+            .iter()
+            .max_by(|&&a, &&b| uct(a).partial_cmp(&uct(b)).unwrap())
+            .unwrap()
+    }
+
+    /// Adds a child for one of the moves not tried yet from `idx`, or returns `idx` if there
+    /// are none that can be made before the event ends.
+    fn expand<R: Rng>(&mut self, idx: usize, rng: &mut R) -> usize {
+        while !self.nodes[idx].untried.is_empty() {
+            let n = self.nodes[idx].untried.len();
+            let mv = self.nodes[idx].untried.swap_remove(rng.gen_range(0, n));
+            let mut child = GameState::new_from_game(self.start.g);
+            child.copy_from(&self.nodes[idx].gs);
+            if upg_seq::play_move(&mut child, &mv) && child.time < child.g.event_time {
+                return self.add_node(child, Some(mv), Some(idx));
+            }
+        }
+        idx
+    }
+
+    /// Finishes the plan through `idx` with `random_play` and scores all of it.
+    fn rollout(&mut self, idx: usize, scratch: &mut GameState) -> f64 {
+        let mut path = Vec::new();
+        let mut at = idx;
+        while let Some(mv) = self.nodes[at].mv {
+            path.push(mv);
+            at = self.nodes[at].parent.unwrap();
+        }
+        path.reverse();
+        let mut seq = self.prefix.clone();
+        seq.append(&mut path);
+
+        let mut sim = GameState::new_from_game(self.start.g);
+        sim.copy_from(&self.nodes[idx].gs);
+        seq.extend(improve::random_play(&mut sim, &Vec::new()));
+        let s = upg_seq::score_spare(&self.start, scratch, &seq);
+        match &self.best {
+            Some(best) if best.1 >= s => {}
+            _ => self.best = Some((seq, s)),
+        }
+        s
+    }
+}
+
+/// The move MCTS rates best from `gs`, with its visits and best playout score, or None if no
+/// more moves can be made before the event ends.
+pub fn best_next_move(gs: &GameState, playouts: usize) -> Option<(Move, u32, f64)> {
+    let mut rng = rand::thread_rng();
+    let tree = Mcts::search(gs, &[], playouts, &mut rng);
+    tree.next_moves().into_iter().next()
+}
+
+/// Builds a whole plan one move at a time, committing to the most visited move after each
+/// search. Returns the best complete plan any playout found, with its score.
+pub fn plan(gs: &GameState, playouts: usize) -> (Vec<Move>, f64) {
+    let g = gs.g;
+    let mut rng = rand::thread_rng();
+    let mut prefix = Vec::new();
+    let mut best = (Vec::new(), f64::NEG_INFINITY);
+    loop {
+        let tree = Mcts::search(gs, &prefix, playouts, &mut rng);
+        if let Some((seq, s)) = &tree.best {
+            if *s > best.1 {
+                best = (seq.clone(), *s);
+            }
+        }
+        match tree.next_moves().first() {
+            Some(&(mv, visits, top)) => {
+                println!(
+                    "{:>3}: {:<w$} {:>6} visits, best playout {:.3}, best so far {:.3}",
+                    prefix.len(),
+                    describe(g, &mv),
+                    visits,
+                    top,
+                    best.1,
+                    w = g.upg_name_len + 4
+                );
+                prefix.push(mv);
+            }
+            None => break,
+        }
+    }
+    best
+}
+
+fn describe(g: &Game, mv: &Move) -> String {
+    match mv {
+        Move::LvlUp(lvlup) => format!("{} -> {}", g.upgrades[lvlup.uidx].get_name(), lvlup.level),
+        Move::Switch(sw) => format!("switch {}", sw.to_string(g)),
+    }
+}
+
+pub fn mcts_main(
+    gs: GameState,
+    output_file: &str,
+    playouts: usize,
+    next_only: bool,
+) -> Result<(), Box<dyn Error>> {
+    let g = gs.g;
+    if next_only {
+        let mut rng = rand::thread_rng();
+        let tree = Mcts::search(&gs, &[], playouts, &mut rng);
+        let next = tree.next_moves();
+        if next.is_empty() {
+            println!("No moves left before the event ends");
+        }
+        for (mv, visits, top) in next {
+            println!(
+                "{:>5} {:<w$} {:>6} visits, best playout {:.3} hours to spare",
+                mv.to_string(g),
+                describe(g, &mv),
+                visits,
+                top,
+                w = g.upg_name_len + 4
+            );
+        }
+        return Ok(());
+    }
+
+    let (seq, score) = plan(&gs, playouts);
+    println!(
+        "Best plan has {} moves, {:.3} hours to spare",
+        seq.len(),
+        score
+    );
+    if !output_file.is_empty() {
+        let mut file = File::create(output_file)?;
+        for mv in &seq {
+            writeln!(&mut file, "{}", mv.to_string(g))?;
+        }
+        println!("Moves written to {}", output_file);
+    }
+    schedule::print_sessions(&gs, &seq);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Boost, Producer, Upgrade};
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_search() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let mut rng = rand::thread_rng();
+        let tree = Mcts::search(&gs, &[], 200, &mut rng);
+        let next = tree.next_moves();
+        assert!(!next.is_empty());
+        assert_eq!(next.iter().map(|n| n.1).sum::<u32>(), 200);
+
+        let (seq, s) = tree.best.unwrap();
+        assert_eq!(upg_seq::repair(&seq, &gs), seq);
+        let mut scratch = GameState::new_from_game(&g);
+        assert_eq!(upg_seq::score_spare(&gs, &mut scratch, &seq).max(s), s);
+    }
+
+    #[test]
+    fn test_hint_dominant() {
+        // A free mine makes gold, which buys a shop that makes points or a trinket that
+        // does nothing, so the shop should come first.
+        let mut g = Game::new();
+        g.set_resources(&["gold"]);
+        g.nres = 1;
+        g.goal = 1.0e6;
+        let mut mine = Producer::new("Mine".to_string());
+        mine.spawn_time = 1.0;
+        mine.costs = vec![vec![0]];
+        mine.produces = vec![vec![1]];
+        mine.produces2 = mine.produces.clone();
+        mine.points = vec![0.0];
+        mine.points2 = mine.points.clone();
+        g.add_upgrade(Upgrade::Producer(mine));
+        let mut shop = Producer::new("Shop".to_string());
+        shop.spawn_time = 1.0;
+        shop.costs = vec![vec![1000]];
+        shop.produces = vec![vec![0]];
+        shop.produces2 = shop.produces.clone();
+        shop.points = vec![10.0];
+        shop.points2 = shop.points.clone();
+        g.add_upgrade(Upgrade::Producer(shop));
+        let mut trinket = Boost::new("Trinket".to_string());
+        trinket.costs = vec![vec![1000]];
+        trinket.res_bonus = vec![vec![0]];
+        trinket.pt_mult = vec![0.0];
+        trinket.time_mod = vec![0.0];
+        g.add_upgrade(Upgrade::Boost(trinket));
+        g.find_prereqs();
+
+        let gs = GameState::new_from_game(&g);
+        let (mv, visits, _) = best_next_move(&gs, 100).unwrap();
+        assert_eq!(mv, Move::LvlUp(LvlUp { uidx: 1, level: 1 }));
+        assert!(visits > 50);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::game::{Game, Move};
+use crate::game_state::GameState;
+use crate::jitter::{Jitter, Percentile};
+use crate::relax;
+use crate::upg_seq;
+
+/// Something to maximise when optimizing a move sequence. Implementations play `seq` from `gs`
+/// using `scratch` as working space, and return a score where higher is better.
+pub trait Objective: Send + Sync {
+    /// Plays `seq` on from wherever `scratch` is and scores the result.
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64;
+
+    /// Whether `score_rest` can carry on from `scratch` after moves were made with
+    /// `upg_seq::play_move`, i.e. those moves haven't already settled the score. If not,
+    /// `score_rest` is only ever called from the starting state.
+    fn resumable(&self, _scratch: &GameState) -> bool {
+        true
+    }
+
+    fn score(&self, gs: &GameState, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        scratch.copy_from(gs);
+        scratch.update_rates();
+        self.score_rest(scratch, seq)
+    }
+
+    /// The best score any plan from `gs` could get, if there's a cheap way to tell.
+    fn upper_bound(&self, _gs: &GameState) -> Option<f64> {
+        None
+    }
+
+    /// Whether the score takes off `Schedule::checkin_penalty` for each check-in.
+    fn charges_checkins(&self) -> bool {
+        false
+    }
+
+    /// What the score measures, for progress messages.
+    fn describe(&self) -> String;
+}
+
+impl<O: Objective + ?Sized> Objective for Box<O> {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        (**self).score_rest(scratch, seq)
+    }
+
+    fn resumable(&self, scratch: &GameState) -> bool {
+        (**self).resumable(scratch)
+    }
+
+    fn upper_bound(&self, gs: &GameState) -> Option<f64> {
+        (**self).upper_bound(gs)
+    }
+
+    fn charges_checkins(&self) -> bool {
+        (**self).charges_checkins()
+    }
+
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+}
+
+/// Hours to spare when reaching `Game::goal`, as `upg_seq::score_spare`. The default.
+pub struct Spare;
+
+impl Objective for Spare {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        upg_seq::score_spare_rest(scratch, seq, scratch.g.goal)
+    }
+
+    fn resumable(&self, scratch: &GameState) -> bool {
+        scratch.points <= scratch.g.goal
+    }
+
+    fn upper_bound(&self, gs: &GameState) -> Option<f64> {
+        Some(relax::spare_for(gs, gs.g.goal))
+    }
+
+    fn charges_checkins(&self) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        "hours to spare".to_string()
+    }
+}
+
+/// Points at the end of the event, as `upg_seq::score`.
+pub struct Points;
+
+impl Objective for Points {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        upg_seq::score_rest(scratch, seq)
+    }
+
+    fn upper_bound(&self, gs: &GameState) -> Option<f64> {
+        Some(relax::points_by(gs, gs.g.event_time))
+    }
+
+    fn describe(&self) -> String {
+        "points".to_string()
+    }
+}
+
+/// Points at a given time into the event, in seconds. Moves after that time don't count.
+pub struct PointsAt(pub f64);
+
+impl Objective for PointsAt {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        upg_seq::score_at_rest(scratch, seq, self.0)
+    }
+
+    fn resumable(&self, scratch: &GameState) -> bool {
+        scratch.time <= self.0
+    }
+
+    fn upper_bound(&self, gs: &GameState) -> Option<f64> {
+        Some(relax::points_by(gs, self.0))
+    }
+
+    fn describe(&self) -> String {
+        format!("points at {:.2}h", self.0 / 60.0 / 60.0)
+    }
+}
+
+/// Hours to spare when reaching a milestone number of points, instead of the game's goal.
+pub struct Milestone(pub f64);
+
+impl Objective for Milestone {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        upg_seq::score_spare_rest(scratch, seq, self.0)
+    }
+
+    fn resumable(&self, scratch: &GameState) -> bool {
+        scratch.points <= self.0
+    }
+
+    fn upper_bound(&self, gs: &GameState) -> Option<f64> {
+        Some(relax::spare_for(gs, self.0))
+    }
+
+    fn charges_checkins(&self) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        format!("hours to spare reaching {:.4e}", self.0)
+    }
+}
+
+/// Amount of one resource left at the end of the event.
+pub struct FinalResource {
+    pub ires: usize,
+    pub name: String,
+}
+
+impl Objective for FinalResource {
+    fn score_rest(&self, scratch: &mut GameState, seq: &[Move]) -> f64 {
+        upg_seq::score_rest(scratch, seq);
+        scratch.res_amt[self.ires]
+    }
+
+    fn describe(&self) -> String {
+        format!("final {}", self.name)
+    }
+}
+
+/// Game states after each move of a base sequence, so that variations of it can be scored
+/// without replaying the moves they share with it.
+pub struct Checkpoints<'a> {
+    base: Vec<Move>,
+    states: Vec<GameState<'a>>, // states[k] is after the first k moves, while resumable
+}
+
+impl<'a> Checkpoints<'a> {
+    pub fn new<O: Objective + ?Sized>(
+        gs: &GameState<'a>,
+        seq: &[Move],
+        obj: &O,
+    ) -> Checkpoints<'a> {
+        let mut start = GameState::new_from_game(gs.g);
+        start.copy_from(gs);
+        start.update_rates();
+        let mut states = Vec::with_capacity(seq.len() + 1);
+        states.push(start);
+        for mv in seq {
+            let mut next = GameState::new_from_game(gs.g);
+            next.copy_from(&states[states.len() - 1]);
+            if !upg_seq::play_move(&mut next, mv) || !obj.resumable(&next) {
+                break;
+            }
+            states.push(next);
+        }
+        Checkpoints {
+            base: seq.to_vec(),
+            states,
+        }
+    }
+
+    /// Same as `obj.score(gs, scratch, seq)`, but starts from the last checkpoint before `seq`
+    /// departs from the base sequence.
+    pub fn score<O: Objective + ?Sized>(
+        &self,
+        obj: &O,
+        scratch: &mut GameState,
+        seq: &[Move],
+    ) -> f64 {
+        let common = self
+            .base
+            .iter()
+            .zip(seq.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let k = common.min(self.states.len() - 1);
+        scratch.copy_from(&self.states[k]);
+        obj.score_rest(scratch, &seq[k..])
+    }
+}
+
+/// `--horizon`: points `hours` after `gs`, however long the event goes on after that.
+pub fn horizon(hours: f64, gs: &GameState) -> Result<Box<dyn Objective>, String> {
+    let at = gs.time + hours * 60.0 * 60.0;
+    if hours <= 0.0 || at > gs.g.event_time {
+        return Err(format!(
+            "horizon of {} hours is not within the {:.2} hours left of the event",
+            hours,
+            (gs.g.event_time - gs.time) / 60.0 / 60.0
+        ));
+    }
+    Ok(Box::new(PointsAt(at)))
+}
+
+/// Parses the `--objective` option: `spare`, `points`, `points@<hours>`, `milestone:<points>`,
+/// `res:<resource name>` or `p<percentile>` of spare time under `jitter`.
+pub fn from_arg(arg: &str, g: &Game, jitter: &Jitter) -> Result<Box<dyn Objective>, String> {
+    let bad_num = |s: &str| format!("bad number {:?} in objective {:?}", s, arg);
+    if arg == "spare" {
+        Ok(Box::new(Spare))
+    } else if arg == "points" {
+        Ok(Box::new(Points))
+    } else if let Some(hours) = arg.strip_prefix("points@") {
+        let hours: f64 = hours
+            .trim_end_matches('h')
+            .parse()
+            .map_err(|_| bad_num(hours))?;
+        Ok(Box::new(PointsAt(hours * 60.0 * 60.0)))
+    } else if let Some(points) = arg.strip_prefix("milestone:") {
+        let points: f64 = points
+            .replace(",", "")
+            .parse()
+            .map_err(|_| bad_num(points))?;
+        Ok(Box::new(Milestone(points)))
+    } else if let Some(name) = arg.strip_prefix("res:") {
+        let ires = g
+            .res_names
+            .iter()
+            .position(|r| r.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown resource {:?}", name))?;
+        Ok(Box::new(FinalResource {
+            ires,
+            name: g.res_names[ires].clone(),
+        }))
+    } else if let Some(pct) = arg.strip_prefix('p') {
+        let pct: f64 = pct.parse().map_err(|_| bad_num(pct))?;
+        if !(0.0..=100.0).contains(&pct) {
+            return Err(format!("percentile {} is not between 0 and 100", pct));
+        }
+        Ok(Box::new(Percentile {
+            jitter: jitter.clone(),
+            pct,
+        }))
+    } else {
+        Err(format!(
+            "unknown objective {:?}, use spare, points, points@<hours>, milestone:<points>, \
+             res:<name> or p<percentile>",
+            arg
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{lvlups, opening, other_tower};
+
+    #[test]
+    fn test_objectives_agree() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let mut scratch = GameState::new_from_game(&g);
+        let seq = opening();
+
+        let points = Points.score(&gs, &mut scratch, &seq);
+        let at_end = PointsAt(g.event_time).score(&gs, &mut scratch, &seq);
+        assert_eq!(points, at_end);
+        assert!(PointsAt(60.0).score(&gs, &mut scratch, &seq) < points);
+        assert_eq!(
+            Milestone(g.goal).score(&gs, &mut scratch, &seq),
+            Spare.score(&gs, &mut scratch, &seq)
+        );
+
+        let jitter = Jitter::from_args("exp:10", 10).unwrap();
+        assert_eq!(
+            from_arg("points@12", &g, &jitter).unwrap().describe(),
+            "points at 12.00h"
+        );
+        assert_eq!(
+            from_arg("res:ruby", &g, &jitter).unwrap().describe(),
+            "final Ruby"
+        );
+        assert!(from_arg("res:gold", &g, &jitter).is_err());
+        assert!(from_arg("p10", &g, &jitter).is_ok());
+        assert!(from_arg("p110", &g, &jitter).is_err());
+
+        let mut later = GameState::new_from_game(&g);
+        later.time = 24.0 * 60.0 * 60.0;
+        assert_eq!(
+            horizon(12.0, &later).unwrap().describe(),
+            "points at 36.00h"
+        );
+        assert!(horizon(49.0, &later).is_err());
+        assert!(horizon(0.0, &later).is_err());
+    }
+
+    #[test]
+    fn test_checkpoints_match_full_score() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let mut scratch = GameState::new_from_game(&g);
+        let mut seq = opening();
+        seq.extend(lvlups(&[(1, 3), (0, 5)]));
+        let objectives: Vec<Box<dyn Objective>> = vec![
+            Box::new(Spare),
+            Box::new(Points),
+            Box::new(PointsAt(30.0 * 60.0)),
+            Box::new(Milestone(100.0)),
+            Box::new(Percentile {
+                jitter: Jitter::from_args("exp:10", 5).unwrap(),
+                pct: 10.0,
+            }),
+        ];
+        for obj in &objectives {
+            let checkpoints = Checkpoints::new(&gs, &seq, obj);
+            let mut variations = upg_seq::Variations::try_seqs(&seq, &g);
+            while let Some(new_seq) = upg_seq::VarIter::next(&mut variations) {
+                let full = obj.score(&gs, &mut scratch, new_seq);
+                assert_eq!(checkpoints.score(obj, &mut scratch, new_seq), full);
+            }
+        }
+    }
+}
@@ -4,6 +4,9 @@ use std::collections::HashMap;
 
 use crate::game::Game;
 use crate::game_state::GameState;
+use crate::mcts;
+
+const HINT_PLAYOUTS: usize = 2000;
 
 pub fn play(g: &Game, gs: &mut GameState) {
     gs.update_rates();
@@ -13,7 +16,7 @@ pub fn play(g: &Game, gs: &mut GameState) {
 
         let mut options = Vec::new();
         for iupg in 0..g.upgrades.len() {
-            let ttl = gs.time_till_lvlup(iupg);
+            let ttl = gs.lvlup_wait(iupg, 0.0);
             if let Some(ttl) = ttl {
                 options.push((iupg as usize, ttl));
             }
@@ -41,7 +44,7 @@ pub fn play(g: &Game, gs: &mut GameState) {
             valid_ch.insert(iupg.to_string(), (*iupg, *ttl));
         }
         let (iupg, ttl) = loop {
-            print!("Enter choice (ex to exit): ");
+            print!("Enter choice (hint for a suggestion, ex to exit): ");
             let mut choice = String::new();
             let bytes = io::stdin()
                 .read_line(&mut choice)
@@ -53,6 +56,19 @@ pub fn play(g: &Game, gs: &mut GameState) {
                 break 'moves;
             }
             let choice_trim = choice.trim();
+            if choice_trim == "hint" {
+                match mcts::best_next_move(gs, HINT_PLAYOUTS) {
+                    Some((mv, visits, top)) => println!(
+                        "Try {}: {} of {} playouts, the best with {:.3} hours to spare",
+                        mv.to_string(g),
+                        visits,
+                        HINT_PLAYOUTS,
+                        top
+                    ),
+                    None => println!("No moves left before the event ends"),
+                }
+                continue;
+            }
             match valid_ch.get(choice_trim) {
                 Some(ch) => {
                     break ch;
@@ -63,7 +79,7 @@ pub fn play(g: &Game, gs: &mut GameState) {
             }
         };
         let iupg = *iupg;
-        let ttl = ttl.ceil() + 1.0; // extra padding for "safety"
+        let ttl = *ttl; // already padded for "safety" by move_wait
 
         println!(
             "advancing {} seconds to upgrade {} -> {}",
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde_json::Value;
+
+static JSONL: AtomicBool = AtomicBool::new(false);
+
+/// Parses `--log-format`: `text` for the usual progress lines, or `jsonl` for one JSON object
+/// per event instead, for scripts to read.
+pub fn set_format(arg: &str) -> Result<(), String> {
+    match arg {
+        "text" => JSONL.store(false, Ordering::Relaxed),
+        "jsonl" => JSONL.store(true, Ordering::Relaxed),
+        _ => return Err(format!("unknown log format {:?}, use text or jsonl", arg)),
+    }
+    Ok(())
+}
+
+/// Whether progress goes out as JSON lines.
+pub fn jsonl() -> bool {
+    JSONL.load(Ordering::Relaxed)
+}
+
+/// `println!` for lines meant for people: on stdout as usual, but on stderr with
+/// `--log-format jsonl`, so that stdout has nothing but events.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::progress::jsonl() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints one event, an object with an "event" field saying what happened, on its own line.
+pub fn event(value: Value) {
+    println!("{}", value);
+}
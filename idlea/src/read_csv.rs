@@ -2,7 +2,8 @@ use std::error::Error;
 use std::fs::File;
 //use std::io::prelude::*;
 
-use crate::game::{Boost, Game, Producer, Upgrade};
+use crate::game::{Boost, Game, Offline, Producer, Upgrade};
+use crate::say;
 
 pub fn game_from_csv(csv_file: &str) -> Result<Game, Box<dyn Error>> {
     let file = File::open(csv_file)?;
@@ -17,17 +18,32 @@ pub fn game_from_csv(csv_file: &str) -> Result<Game, Box<dyn Error>> {
     //println!("{:?}", row);
     game.name = row[0].to_string();
 
-    // goal points
-    let mut next_is_target = false;
-    for next_col in row.iter().skip(1) {
-        if next_is_target {
-            println!("Goal: {:?}", next_col);
-            game.goal = local_str_as_i64(next_col) as f64;
-            break;
-        }
-        if next_col == "goal:" {
-            next_is_target = true;
+    // goal points, optional event length "event hours:" (3 days if not given), and optional
+    // offline earning limits: "offline after:" (minutes), "offline max:" (hours) and
+    // "offline rate:" (fraction of normal)
+    let cols: Vec<&str> = row.iter().collect();
+    let mut offline = Offline::default();
+    let mut has_offline = false;
+    for (key, value) in cols.iter().skip(1).zip(cols.iter().skip(2)) {
+        let name = key.trim_end_matches(':');
+        match *key {
+            "goal:" => {
+                say!("Goal: {:?}", value);
+                game.goal = local_str_as_i64(value) as f64;
+            }
+            "event hours:" => game.event_time = value.parse::<f64>()? * 60.0 * 60.0,
+            "offline after:" => offline.after = Offline::check(name, value.parse()?, true)? * 60.0,
+            "offline max:" => {
+                offline.max = Offline::check(name, value.parse()?, false)? * 60.0 * 60.0
+            }
+            "offline rate:" => offline.rate = Offline::check(name, value.parse()?, false)?,
+            _ => continue,
         }
+        has_offline |= key.starts_with("offline");
+    }
+    if has_offline {
+        say!("Offline: {:?}", offline);
+        game.offline = Some(offline);
     }
 
     // resource names
@@ -62,17 +78,17 @@ pub fn game_from_csv(csv_file: &str) -> Result<Game, Box<dyn Error>> {
                 }
                 game.add_upgrade(finished_upg);
             } else {
-                println!("Unexpected separator row");
+                say!("Unexpected separator row");
             }
         } else {
             // Either a new upgrade or a new level, current upgrade
             if let None = upg {
                 let up_lower = upname.to_lowercase();
                 if up_lower.contains("boost") || up_lower.contains("speed") {
-                    println!("Loading boost: {}", upname);
+                    say!("Loading boost: {}", upname);
                     upg = Some(Upgrade::Boost(Boost::new(upname)));
                 } else {
-                    println!("Loading producer: {}", upname);
+                    say!("Loading producer: {}", upname);
                     let mut prod = Producer::new(upname);
                     if &row.as_ref().unwrap()[2] != "" {
                         prod.prod_names.0 = row.as_ref().unwrap()[2].to_string();
@@ -7,6 +7,7 @@ use std::path::Path;
 use serde::{Deserialize};
 use regex::Regex;
 
+use crate::game::{Game, Offline};
 use crate::game_state::GameState;
 
 #[derive(Debug, Deserialize)]
@@ -19,6 +20,18 @@ pub struct InitialConfig {
     points: f64,
     resources: HashMap<String, f64>,
     levels: HashMap<String, usize>,
+    #[serde(default)]
+    offline: Option<OfflineConfig>,
+    #[serde(default)]
+    event_hours: Option<f64>, // overrides the game .csv
+}
+
+/// Offline earning limits, overriding any set in the game .csv.
+#[derive(Debug, Deserialize)]
+struct OfflineConfig {
+    after_mins: Option<f64>,
+    max_hours: Option<f64>,
+    rate: Option<f64>,
 }
 
 pub fn load_config_yaml<P: AsRef<Path>>(config_file: P) -> Result<InitialConfig, Box<dyn Error>> {
@@ -32,6 +45,26 @@ pub fn load_config_yaml<P: AsRef<Path>>(config_file: P) -> Result<InitialConfig,
 }
 
 impl InitialConfig {
+    pub fn fix_game(&self, game: &mut Game) -> Result<(), Box<dyn Error>> {
+        if let Some(hours) = self.event_hours {
+            game.event_time = hours * 60.0 * 60.0;
+        }
+        if let Some(cfg) = &self.offline {
+            let mut offline = game.offline.take().unwrap_or_default();
+            if let Some(mins) = cfg.after_mins {
+                offline.after = Offline::check("after_mins", mins, true)? * 60.0;
+            }
+            if let Some(hours) = cfg.max_hours {
+                offline.max = Offline::check("max_hours", hours, false)? * 60.0 * 60.0;
+            }
+            if let Some(rate) = cfg.rate {
+                offline.rate = Offline::check("rate", rate, false)?;
+            }
+            game.offline = Some(offline);
+        }
+        Ok(())
+    }
+
     pub fn fix_state(&self, gs: &mut GameState) {
         gs.gem_boost = self.gem_boost;
         gs.points = self.points;
@@ -100,4 +133,33 @@ mod tests {
         let tl = time_left_to_time("1d 2h 3m 4s", event_time);
         assert_eq!(tl, event_time - ((((1 * 24) + 2) * 60 + 3) * 60 + 4) as f64);
     }
+
+    #[test]
+    fn test_offline_checked() {
+        let config = |offline: &str| -> InitialConfig {
+            let yaml = format!(
+                "game: g.csv\ngem_boost: 1\ntime_left: 1d\npoints: 0\nresources: {{}}\n\
+                 levels: {{}}\noffline: {{{}}}\n",
+                offline
+            );
+            serde_yaml::from_str(&yaml).unwrap()
+        };
+        let mut game = Game::new();
+        config("after_mins: 0, max_hours: 8, rate: 0.5")
+            .fix_game(&mut game)
+            .unwrap();
+        let offline = game.offline.as_ref().unwrap();
+        assert_eq!(
+            (offline.after, offline.max, offline.rate),
+            (0.0, 8.0 * 3600.0, 0.5)
+        );
+        for (bad, key) in &[
+            ("after_mins: -1", "after_mins"),
+            ("max_hours: 0", "max_hours"),
+            ("rate: -0.5", "rate"),
+        ] {
+            let err = config(bad).fix_game(&mut game).unwrap_err().to_string();
+            assert!(err.starts_with(key), "{}", err);
+        }
+    }
 }
@@ -0,0 +1,275 @@
+use crate::game::{Game, Upgrade};
+use crate::game_state::GameState;
+
+/// A relaxed copy of the game that earns at least as fast as any plan could. Each upgrade gets
+/// to spend everything earned so far on itself alone, so a level counts as bought as soon as
+/// the total ever earned covers its costs, and nothing is ever used up. Each producer adds the
+/// best rate it has for each resource at any level it could be at, with either production and
+/// nothing consumed, so resources made by several producers get all of them.
+struct Relaxed<'a> {
+    g: &'a Game,
+    start: Vec<usize>,    // levels in the real game state
+    levels: Vec<usize>,   // highest level each upgrade could have by now
+    spent: Vec<Vec<f64>>, // what each upgrade's levels above `start` cost, by resource
+    earned: Vec<f64>,     // resources held at the start plus everything earned since
+    gem_boost: i32,
+    commercial_mod: f64,
+    offline_rate: f64,
+    time: f64,
+    points: f64,
+    pt_rate: f64,
+    res_rate: Vec<f64>,
+}
+
+impl<'a> Relaxed<'a> {
+    fn new(gs: &GameState<'a>) -> Relaxed<'a> {
+        let g = gs.g;
+        let mut relaxed = Relaxed {
+            g,
+            start: gs.levels.clone(),
+            levels: gs.levels.clone(),
+            spent: vec![vec![0.0; g.nres]; g.upgrades.len()],
+            earned: gs.res_amt.clone(),
+            gem_boost: gs.gem_boost,
+            commercial_mod: gs.commercial_mod,
+            // Time counts at the normal rate or the offline one, whichever is faster, as a plan
+            // can be online all the time and a game could pay more for being away.
+            offline_rate: g.offline.as_ref().map_or(1.0, |off| off.rate.max(1.0)),
+            time: gs.time,
+            points: gs.points,
+            pt_rate: 0.0,
+            res_rate: vec![0.0; g.nres],
+        };
+        relaxed.update();
+        relaxed
+    }
+
+    /// The cost of the level after `levels[uidx]`, if it isn't maxed.
+    fn next_cost(&self, uidx: usize) -> Option<&Vec<i32>> {
+        self.g.upgrades[uidx].costs().get(self.levels[uidx])
+    }
+
+    /// Buys every level `earned` now covers, then works out the rates.
+    fn update(&mut self) {
+        let g = self.g;
+        for uidx in 0..g.upgrades.len() {
+            while let Some(cost) = g.upgrades[uidx].costs().get(self.levels[uidx]) {
+                let covered = cost
+                    .iter()
+                    .zip(self.spent[uidx].iter().zip(self.earned.iter()))
+                    .all(|(&c, (s, e))| s + (c.max(0) as f64) <= e + 1e-6);
+                if !covered {
+                    break;
+                }
+                for (s, &c) in self.spent[uidx].iter_mut().zip(cost.iter()) {
+                    *s += c.max(0) as f64;
+                }
+                self.levels[uidx] += 1;
+            }
+        }
+
+        let mut bonuses = vec![self.gem_boost; self.g.nres];
+        let mut pt_mult = 0.0;
+        let mut time_fact = 1.0 + self.commercial_mod;
+        for (uidx, upg) in self.g.upgrades.iter().enumerate() {
+            if let Upgrade::Boost(boost) = upg {
+                // The best of each effect over the levels the boost could be at.
+                let mut best_bonus = vec![i32::MIN; self.g.nres];
+                let mut best_mult = f64::MIN;
+                let mut best_time = f64::MAX;
+                for level in self.start[uidx]..=self.levels[uidx] {
+                    for (ires, best) in best_bonus.iter_mut().enumerate() {
+                        let bonus = if level < 1 {
+                            0
+                        } else {
+                            boost.res_bonus[level - 1][ires]
+                        };
+                        *best = (*best).max(bonus);
+                    }
+                    if level < 1 {
+                        best_mult = best_mult.max(0.0);
+                        best_time = best_time.min(0.0);
+                    } else {
+                        best_mult = best_mult.max(boost.pt_mult[level - 1]);
+                        best_time = best_time.min(boost.time_mod[level - 1]);
+                    }
+                }
+                for (bonus, best) in bonuses.iter_mut().zip(best_bonus.iter()) {
+                    *bonus += best;
+                }
+                pt_mult += best_mult;
+                time_fact += best_time;
+            }
+        }
+
+        self.pt_rate = 0.0;
+        for r in &mut self.res_rate {
+            *r = 0.0;
+        }
+        for (uidx, upg) in self.g.upgrades.iter().enumerate() {
+            if let Upgrade::Producer(prod) = upg {
+                let mut best_pts: f64 = 0.0;
+                let mut best_res = vec![0.0; self.g.nres];
+                for level in self.start[uidx].max(1)..=self.levels[uidx] {
+                    for prod2 in &[false, true] {
+                        let produces = if *prod2 {
+                            &prod.produces2[level - 1]
+                        } else {
+                            &prod.produces[level - 1]
+                        };
+                        for (ires, &amt) in produces.iter().enumerate() {
+                            let net = if amt > 0 { amt + bonuses[ires] } else { 0 };
+                            let rate = net.max(0) as f64 / prod.spawn_time;
+                            best_res[ires] = f64::max(best_res[ires], rate);
+                        }
+                        best_pts = best_pts.max(prod.get_pt_rate(level, *prod2));
+                    }
+                }
+                self.pt_rate += best_pts;
+                for (r, best) in self.res_rate.iter_mut().zip(best_res.iter()) {
+                    *r += best;
+                }
+            }
+        }
+        let scale = if time_fact > 0.0 {
+            self.offline_rate / time_fact
+        } else {
+            f64::INFINITY
+        };
+        self.pt_rate *= (1.0 + pt_mult) * scale;
+        for r in &mut self.res_rate {
+            *r *= scale;
+        }
+    }
+
+    /// Seconds until `earned` covers another level of some upgrade.
+    fn till_next_level(&self) -> f64 {
+        let mut soonest = f64::INFINITY;
+        for uidx in 0..self.g.upgrades.len() {
+            if let Some(cost) = self.next_cost(uidx) {
+                let mut wait: f64 = 0.0;
+                for (ires, &c) in cost.iter().enumerate() {
+                    let need = self.spent[uidx][ires] + c.max(0) as f64 - self.earned[ires];
+                    if need > 0.0 {
+                        wait = wait.max(need / self.res_rate[ires]);
+                    }
+                }
+                soonest = soonest.min(wait);
+            }
+        }
+        soonest
+    }
+
+    /// Runs the relaxed game until `until` seconds into the event, or until it has `goal`
+    /// points.
+    fn run(&mut self, until: f64, goal: f64) {
+        while self.time < until && self.points < goal {
+            let mut dt = self.till_next_level().min(until - self.time);
+            if self.pt_rate > 0.0 && self.points + self.pt_rate * dt >= goal {
+                dt = (goal - self.points) / self.pt_rate;
+            }
+            self.time += dt;
+            self.points += self.pt_rate * dt;
+            for (e, r) in self.earned.iter_mut().zip(self.res_rate.iter()) {
+                *e += r * dt;
+            }
+            self.update();
+        }
+    }
+}
+
+/// The most points any plan from `gs` could have `horizon` seconds into the event.
+pub fn points_by(gs: &GameState, horizon: f64) -> f64 {
+    let mut relaxed = Relaxed::new(gs);
+    relaxed.run(horizon.min(gs.g.event_time), f64::INFINITY);
+    relaxed.points
+}
+
+/// The most hours to spare any plan from `gs` could have when it reaches `goal` points,
+/// counted as `upg_seq::score_spare` does.
+pub fn spare_for(gs: &GameState, goal: f64) -> f64 {
+    let mut relaxed = Relaxed::new(gs);
+    relaxed.run(gs.g.event_time, goal);
+    let spare = if relaxed.points >= goal {
+        gs.g.event_time - relaxed.time
+    } else if relaxed.pt_rate == 0.0 {
+        -1.0e30
+    } else {
+        -(goal - relaxed.points) / relaxed.pt_rate
+    };
+    spare / 60.0 / 60.0
+}
+
+pub fn upper_bound_main(gs: GameState) {
+    let g = gs.g;
+    println!(
+        "No plan can make more than {:.4e} {} by the end of the event",
+        points_by(&gs, g.event_time),
+        g.points_name
+    );
+    println!(
+        "No plan can reach the goal of {:.4e} with more than {:.3} hours to spare",
+        g.goal,
+        spare_for(&gs, g.goal)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Producer;
+    use crate::solve::Solver;
+    use crate::tests::other_tower;
+    use crate::upg_seq;
+    use std::time::Duration;
+
+    #[test]
+    fn test_bounds_beat_plan() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        gs.update_rates();
+        let mut scratch = GameState::new_from_game(&g);
+        for _ in 0..20 {
+            scratch.copy_from(&gs);
+            let seq = crate::improve::random_play(&mut scratch, &vec![]);
+            assert!(points_by(&gs, g.event_time) >= upg_seq::score(&gs, &mut scratch, &seq));
+            assert!(spare_for(&gs, g.goal) >= upg_seq::score_spare(&gs, &mut scratch, &seq));
+        }
+        assert!(points_by(&gs, 0.0) == gs.points);
+    }
+
+    #[test]
+    fn test_producers_add_up() {
+        // Two free mines make gold at the same rate, and a shop turns gold into points.
+        let mut g = Game::new();
+        g.set_resources(&["gold"]);
+        g.nres = 1;
+        g.event_time = 2.0 * 60.0 * 60.0;
+        for name in &["Mine", "Mine 2"] {
+            let mut mine = Producer::new(name.to_string());
+            mine.spawn_time = 1.0;
+            mine.costs = vec![vec![0]];
+            mine.produces = vec![vec![1]];
+            mine.produces2 = mine.produces.clone();
+            mine.points = vec![0.0];
+            mine.points2 = mine.points.clone();
+            g.add_upgrade(Upgrade::Producer(mine));
+        }
+        let mut shop = Producer::new("Shop".to_string());
+        shop.spawn_time = 1.0;
+        shop.costs = vec![vec![1000], vec![2000]];
+        shop.produces = vec![vec![0], vec![0]];
+        shop.produces2 = shop.produces.clone();
+        shop.points = vec![1.0, 3.0];
+        shop.points2 = shop.points.clone();
+        g.add_upgrade(Upgrade::Producer(shop));
+        g.find_prereqs();
+
+        let gs = GameState::new_from_game(&g);
+        let solver = Solver::solve(&gs, 2, Duration::from_secs(60));
+        assert!(!solver.timed_out);
+        assert!(solver.best > 0.0);
+        assert!(points_by(&gs, g.event_time) >= solver.best);
+    }
+}
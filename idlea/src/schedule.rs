@@ -0,0 +1,191 @@
+use chrono::{DateTime, Duration, FixedOffset, Timelike};
+
+use crate::game::{Game, Move};
+use crate::game_state::GameState;
+use crate::say;
+use crate::upg_seq::{self, TimedMove};
+
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// When the player can actually make moves: daily awake windows in the local time of the
+/// event start. Moves made within `session` seconds of each other count as one check-in.
+#[derive(Debug)]
+pub struct Schedule {
+    pub start: DateTime<FixedOffset>,
+    pub awake: Vec<(f64, f64)>, // (from, to) in seconds since local midnight
+    pub session: f64,
+    pub checkin_penalty: f64, // hours taken off score_spare per check-in
+}
+
+impl Schedule {
+    /// Parses awake windows like "8-23" or "7:30-12,13-23:30" (windows may wrap past midnight).
+    /// `start` is the real event start in RFC 3339, whose UTC offset gives the local time.
+    pub fn from_args(
+        awake: &str,
+        start: &str,
+        session_mins: f64,
+        checkin_penalty: f64,
+    ) -> Result<Schedule, String> {
+        let start = DateTime::parse_from_rfc3339(start)
+            .map_err(|err| format!("bad --start time {:?}: {}", start, err))?;
+        let mut windows = Vec::new();
+        for window in awake.split(',') {
+            let mut ends = window.split('-');
+            let from = ends.next().map(time_of_day);
+            let to = ends.next().map(time_of_day);
+            match (from, to, ends.next()) {
+                (Some(Some(from)), Some(Some(to)), None) if from != to => windows.push((from, to)),
+                _ => return Err(format!("bad awake window {:?}, use e.g. 7:30-23", window)),
+            }
+        }
+        Ok(Schedule {
+            start,
+            awake: windows,
+            session: session_mins * 60.0,
+            checkin_penalty,
+        })
+    }
+
+    /// The earliest time at or after `t` (seconds into the event) when the player is awake.
+    pub fn next_available(&self, t: f64) -> f64 {
+        let start_tod = self.start.num_seconds_from_midnight() as f64;
+        let tod = (start_tod + t).rem_euclid(DAY);
+        let mut wait = DAY;
+        for &(from, to) in &self.awake {
+            let inside = if from < to {
+                from <= tod && tod < to
+            } else {
+                tod >= from || tod < to
+            };
+            if inside {
+                return t;
+            }
+            wait = wait.min((from - tod).rem_euclid(DAY));
+        }
+        t + wait
+    }
+
+    /// The earliest time at or after `t` when the player is asleep, or infinity if never.
+    pub fn next_unavailable(&self, t: f64) -> f64 {
+        let start_tod = self.start.num_seconds_from_midnight() as f64;
+        let mut t = t;
+        for _ in 0..=self.awake.len() {
+            let tod = (start_tod + t).rem_euclid(DAY);
+            let window = self.awake.iter().find(|&&(from, to)| {
+                if from < to {
+                    from <= tod && tod < to
+                } else {
+                    tod >= from || tod < to
+                }
+            });
+            match window {
+                Some(&(_, to)) => t += (to - tod).rem_euclid(DAY),
+                None => return t,
+            }
+        }
+        f64::INFINITY
+    }
+
+    pub fn wall_time(&self, t: f64) -> DateTime<FixedOffset> {
+        self.start + Duration::seconds(t.round() as i64)
+    }
+}
+
+fn time_of_day(hh_mm: &str) -> Option<f64> {
+    let mut parts = hh_mm.trim().split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let mins: f64 = match parts.next() {
+        Some(mins) => mins.parse().ok()?,
+        None => 0.0,
+    };
+    if parts.next().is_some() || !(0.0..=24.0).contains(&hours) || !(0.0..60.0).contains(&mins) {
+        return None;
+    }
+    Some((hours * 60.0 + mins) * 60.0)
+}
+
+/// Splits a timeline into check-ins: runs of moves where each falls within `window` seconds of
+/// the first move of its run.
+pub fn group_checkins(timed: &[TimedMove], window: f64) -> Vec<&[TimedMove]> {
+    let mut checkins = Vec::new();
+    let mut first = 0;
+    for (i, tm) in timed.iter().enumerate() {
+        if tm.time - timed[first].time > window {
+            checkins.push(&timed[first..i]);
+            first = i;
+        }
+    }
+    if first < timed.len() {
+        checkins.push(&timed[first..]);
+    }
+    checkins
+}
+
+/// Prints a sequence as the check-in sessions it takes under the game's schedule.
+pub fn print_sessions(gs: &GameState, seq: &[Move]) {
+    let g: &Game = gs.g;
+    let sched = match &g.schedule {
+        Some(sched) => sched,
+        None => return,
+    };
+    let mut scratchpad = GameState::new_from_game(g);
+    let timed = upg_seq::timeline(gs, &mut scratchpad, seq);
+    let checkins = group_checkins(&timed, sched.session);
+    say!("{} check-ins:", checkins.len());
+    for moves in checkins {
+        let names: Vec<String> = moves
+            .iter()
+            .map(|tm| match &tm.mv {
+                Move::LvlUp(lvlup) => {
+                    format!("{} {}", g.upgrades[lvlup.uidx].get_name(), lvlup.level)
+                }
+                Move::Switch(sw) => format!("switch {}", sw.to_string(g)),
+            })
+            .collect();
+        say!(
+            "  {} (+{:.2}h): {}",
+            sched.wall_time(moves[0].time).format("%a %H:%M"),
+            moves[0].time / 60.0 / 60.0,
+            names.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::LvlUp;
+
+    #[test]
+    fn test_next_available() {
+        let sched = Schedule::from_args("8-23", "2020-01-31T22:00:00-07:00", 10.0, 0.0).unwrap();
+        let hour = 60.0 * 60.0;
+        assert_eq!(sched.next_available(0.0), 0.0);
+        assert_eq!(sched.next_available(0.5 * hour), 0.5 * hour);
+        assert_eq!(sched.next_available(1.0 * hour), 10.0 * hour); // 23:00 -> 8:00
+        assert_eq!(sched.next_available(9.5 * hour), 10.0 * hour);
+
+        let night_owl = Schedule::from_args("20-2", "2020-01-31T01:00:00Z", 10.0, 0.0).unwrap();
+        assert_eq!(night_owl.next_available(0.5 * hour), 0.5 * hour);
+        assert_eq!(night_owl.next_available(2.0 * hour), 19.0 * hour);
+
+        assert!(Schedule::from_args("8", "2020-01-31T01:00:00Z", 10.0, 0.0).is_err());
+        assert!(Schedule::from_args("8-25", "2020-01-31T01:00:00Z", 10.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_group_checkins() {
+        let timed: Vec<TimedMove> = [0.0, 100.0, 500.0, 700.0, 5000.0]
+            .iter()
+            .map(|&time| TimedMove {
+                time,
+                mv: Move::LvlUp(LvlUp { uidx: 0, level: 1 }),
+            })
+            .collect();
+        let sizes: Vec<usize> = group_checkins(&timed, 600.0)
+            .iter()
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(sizes, vec![3, 1, 1]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::error::Error;
+
+use hashbrown::HashMap;
+
+use crate::game::{Game, Move};
+use crate::game_state::GameState;
+use crate::upg_seq;
+
+/// How a move present in both sequences was shifted, relative to the moves that kept their
+/// order. Same operations as `Variations` makes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shift {
+    Kept,
+    Advanced,
+    Postponed,
+}
+
+pub fn diff_main(
+    gs: GameState,
+    file_a: &str,
+    file_b: &str,
+    lenient: bool,
+) -> Result<(), Box<dyn Error>> {
+    let seq_a = upg_seq::load_sequence(file_a, &gs, lenient)?;
+    let seq_b = upg_seq::load_sequence(file_b, &gs, lenient)?;
+    let g = gs.g;
+    let mut scratchpad = GameState::new_from_game(g);
+
+    let points_a = upg_seq::score(&gs, &mut scratchpad, &seq_a);
+    let spare_a = upg_seq::score_spare(&gs, &mut scratchpad, &seq_a);
+    let points_b = upg_seq::score(&gs, &mut scratchpad, &seq_b);
+    let spare_b = upg_seq::score_spare(&gs, &mut scratchpad, &seq_b);
+    println!(
+        "A: {} moves, {:.4} points, {:.3} hours to spare ({})",
+        seq_a.len(),
+        points_a,
+        spare_a,
+        file_a
+    );
+    println!(
+        "B: {} moves, {:.4} points, {:.3} hours to spare ({})",
+        seq_b.len(),
+        points_b,
+        spare_b,
+        file_b
+    );
+
+    let times_a = upg_seq::timeline(&gs, &mut scratchpad, &seq_a);
+    let times_b = upg_seq::timeline(&gs, &mut scratchpad, &seq_b);
+    match first_divergence(&seq_a, &seq_b) {
+        None => {
+            println!("Sequences are identical");
+            return Ok(());
+        }
+        Some(i) => {
+            println!(
+                "First divergence at move #{}: A has {} at {}, B has {} at {}",
+                i,
+                describe(g, seq_a.get(i)),
+                hours(times_a.get(i).map(|tm| tm.time)),
+                describe(g, seq_b.get(i)),
+                hours(times_b.get(i).map(|tm| tm.time))
+            );
+        }
+    }
+
+    let pairs = pair_moves(&seq_a, &seq_b);
+    let shifts = classify(&pairs);
+    println!("Moves advanced or postponed in B:");
+    for (&(ia, ib), shift) in pairs.iter().zip(shifts.iter()) {
+        if *shift != Shift::Kept {
+            println!(
+                "  {:>5} {:<w$} {:?} from #{} to #{}",
+                format!("{}", seq_a[ia]),
+                describe(g, Some(&seq_a[ia])),
+                shift,
+                ia,
+                ib,
+                w = g.upg_name_len + 12
+            );
+        }
+    }
+    let only_a = unpaired(seq_a.len(), pairs.iter().map(|p| p.0));
+    let only_b = unpaired(seq_b.len(), pairs.iter().map(|p| p.1));
+    for (name, seq, only) in [("A", &seq_a, only_a), ("B", &seq_b, only_b)].iter() {
+        for &i in only {
+            println!("  only in {}: #{} {}", name, i, describe(g, Some(&seq[i])));
+        }
+    }
+
+    println!(
+        "{:>4} {:>4} {:<w$} {:>8} {:>8} {:>12} {:>9}",
+        "#A",
+        "#B",
+        "move",
+        "tA (h)",
+        "tB (h)",
+        "points B-A",
+        "spare B-A",
+        w = g.upg_name_len + 12
+    );
+    for &(ia, ib) in &pairs {
+        let pts_a = upg_seq::score(&gs, &mut scratchpad, &seq_a[..=ia]);
+        let spr_a = upg_seq::score_spare(&gs, &mut scratchpad, &seq_a[..=ia]);
+        let pts_b = upg_seq::score(&gs, &mut scratchpad, &seq_b[..=ib]);
+        let spr_b = upg_seq::score_spare(&gs, &mut scratchpad, &seq_b[..=ib]);
+        println!(
+            "{:>4} {:>4} {:<w$} {:>8} {:>8} {:>12.4e} {:>9.3}",
+            ia,
+            ib,
+            describe(g, Some(&seq_a[ia])),
+            hours(times_a.get(ia).map(|tm| tm.time)),
+            hours(times_b.get(ib).map(|tm| tm.time)),
+            pts_b - pts_a,
+            spr_b - spr_a,
+            w = g.upg_name_len + 12
+        );
+    }
+    Ok(())
+}
+
+/// Index of the first move where the sequences differ, or None if they're the same.
+pub fn first_divergence(seq_a: &[Move], seq_b: &[Move]) -> Option<usize> {
+    let common = seq_a
+        .iter()
+        .zip(seq_b.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == seq_a.len() && common == seq_b.len() {
+        None
+    } else {
+        Some(common)
+    }
+}
+
+/// Pairs each move of `seq_a` with the same move in `seq_b`, as (index in A, index in B) in A
+/// order. Repeated moves (production switches) are matched by occurrence.
+pub fn pair_moves(seq_a: &[Move], seq_b: &[Move]) -> Vec<(usize, usize)> {
+    let mut where_b: HashMap<(Move, usize), usize> = HashMap::new();
+    let mut seen = HashMap::new();
+    for (ib, mv) in seq_b.iter().enumerate() {
+        let n = seen.entry(*mv).or_insert(0);
+        where_b.insert((*mv, *n), ib);
+        *n += 1;
+    }
+    seen.clear();
+    let mut pairs = Vec::new();
+    for (ia, mv) in seq_a.iter().enumerate() {
+        let n = seen.entry(*mv).or_insert(0);
+        if let Some(&ib) = where_b.get(&(*mv, *n)) {
+            pairs.push((ia, ib));
+        }
+        *n += 1;
+    }
+    pairs
+}
+
+/// Decides which paired moves kept their relative order (a longest increasing run of B
+/// indices) and which were advanced or postponed around them.
+pub fn classify(pairs: &[(usize, usize)]) -> Vec<Shift> {
+    let n = pairs.len();
+    // Longest increasing subsequence of the B indices, O(n^2) is plenty here.
+    let mut best = vec![1; n];
+    let mut prev = vec![None; n];
+    for i in 0..n {
+        for j in 0..i {
+            if pairs[j].1 < pairs[i].1 && best[j] + 1 > best[i] {
+                best[i] = best[j] + 1;
+                prev[i] = Some(j);
+            }
+        }
+    }
+    let mut shifts = vec![Shift::Kept; n];
+    let mut kept = vec![false; n];
+    let mut at = (0..n).max_by_key(|&i| best[i]);
+    while let Some(i) = at {
+        kept[i] = true;
+        at = prev[i];
+    }
+
+    // A moved move is advanced if in B it sits before a kept move it followed in A.
+    for i in 0..n {
+        if kept[i] {
+            continue;
+        }
+        let passed_earlier = (0..i).any(|j| kept[j] && pairs[j].1 > pairs[i].1);
+        shifts[i] = if passed_earlier {
+            Shift::Advanced
+        } else {
+            Shift::Postponed
+        };
+    }
+    shifts
+}
+
+fn unpaired<I: Iterator<Item = usize>>(len: usize, paired: I) -> Vec<usize> {
+    let mut used = vec![false; len];
+    for i in paired {
+        used[i] = true;
+    }
+    (0..len).filter(|&i| !used[i]).collect()
+}
+
+fn describe(g: &Game, mv: Option<&Move>) -> String {
+    match mv {
+        Some(Move::LvlUp(lvlup)) => {
+            format!("{} -> {}", g.upgrades[lvlup.uidx].get_name(), lvlup.level)
+        }
+        Some(Move::Switch(sw)) => format!("switch {}", sw.to_string(g)),
+        None => "nothing".to_string(),
+    }
+}
+
+fn hours(t: Option<f64>) -> String {
+    match t {
+        Some(t) => format!("{:.3}", t / 60.0 / 60.0),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::LvlUp;
+
+    fn seq(uidxs: &[usize]) -> Vec<Move> {
+        uidxs
+            .iter()
+            .map(|&uidx| Move::LvlUp(LvlUp { uidx, level: 1 }))
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let a = seq(&[0, 1, 2, 3, 4]);
+        let b = seq(&[0, 3, 1, 2, 4]);
+        assert_eq!(first_divergence(&a, &b), Some(1));
+        let pairs = pair_moves(&a, &b);
+        assert_eq!(pairs, vec![(0, 0), (1, 2), (2, 3), (3, 1), (4, 4)]);
+        use Shift::*;
+        assert_eq!(classify(&pairs), vec![Kept, Kept, Kept, Advanced, Kept]);
+
+        let b = seq(&[1, 2, 0, 3, 4]);
+        assert_eq!(
+            classify(&pair_moves(&a, &b)),
+            vec![Postponed, Kept, Kept, Kept, Kept]
+        );
+        assert_eq!(first_divergence(&a, &a), None);
+    }
+}
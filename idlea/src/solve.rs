@@ -0,0 +1,335 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+
+use crate::game::{LvlUp, Move, Upgrade};
+use crate::game_state::GameState;
+use crate::upg_seq;
+
+/// Where the search has been with one set of levels, to spot dominated states.
+struct Seen {
+    time: f64,
+    points: f64,
+    res_amt: Vec<f64>,
+}
+
+/// Exact branch-and-bound search for the plan of at most `max_moves` level ups that ends the
+/// event with the most points. Moves are tried depth first, best first; a state is dropped if
+/// `bound` says it can't beat the best plan so far, or if another state with the same levels
+/// got there no later with at least as many points and resources. (Higher levels aren't always
+/// better, since some producers eat resources, so only equal levels are compared.) When the
+/// game has an offline cap or a schedule, getting there sooner can mean a longer, capped wait
+/// later, so states aren't compared at all.
+pub struct Solver {
+    max_moves: usize,
+    prune_dominated: bool,
+    deadline: Instant,
+    path: Vec<Move>,
+    seen: HashMap<Vec<usize>, Vec<Seen>>,
+    pub best: f64,
+    pub best_seq: Vec<Move>,
+    pub open_bound: f64, // best bound of any state left unexplored when time ran out
+    pub timed_out: bool,
+    pub nodes: u64,
+    pub pruned_bound: u64,
+    pub pruned_dominated: u64,
+}
+
+impl Solver {
+    pub fn solve(gs: &GameState, max_moves: usize, budget: Duration) -> Solver {
+        let mut start = GameState::new_from_game(gs.g);
+        start.copy_from(gs);
+        start.update_rates();
+        let mut solver = Solver {
+            max_moves,
+            prune_dominated: gs.g.offline.is_none() && gs.g.schedule.is_none(),
+            deadline: Instant::now() + budget,
+            path: Vec::new(),
+            seen: HashMap::new(),
+            best: f64::NEG_INFINITY,
+            best_seq: Vec::new(),
+            open_bound: f64::NEG_INFINITY,
+            timed_out: false,
+            nodes: 0,
+            pruned_bound: 0,
+            pruned_dominated: 0,
+        };
+        solver.search(&start);
+        solver
+    }
+
+    fn search(&mut self, gs: &GameState) {
+        self.nodes += 1;
+        let value = final_points(gs);
+        if value > self.best {
+            self.best = value;
+            self.best_seq = self.path.clone();
+        }
+        let moves_left = self.max_moves - self.path.len();
+        if moves_left == 0 {
+            return;
+        }
+        let bound = bound(gs, moves_left);
+        if bound <= self.best {
+            self.pruned_bound += 1;
+            return;
+        }
+        if Instant::now() > self.deadline {
+            self.timed_out = true;
+            self.open_bound = self.open_bound.max(bound);
+            return;
+        }
+
+        let mut children = Vec::new();
+        for (uidx, upg) in gs.g.upgrades.iter().enumerate() {
+            let level = gs.levels[uidx] + 1;
+            let mv = Move::LvlUp(LvlUp { uidx, level });
+            if level > upg.costs().len() || !upg_seq::ready(gs, &mv) {
+                continue;
+            }
+            let mut child = GameState::new_from_game(gs.g);
+            child.copy_from(gs);
+            if !upg_seq::play_move(&mut child, &mv) || child.time >= gs.g.event_time {
+                continue;
+            }
+            if self.prune_dominated && self.dominated(&child) {
+                self.pruned_dominated += 1;
+                continue;
+            }
+            children.push((final_points(&child), mv, child));
+        }
+        children.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        for (_, mv, child) in children {
+            self.path.push(mv);
+            self.search(&child);
+            self.path.pop();
+        }
+    }
+
+    /// Whether a state with the same levels was already reached no later, with at least as
+    /// many points and resources. If not, `gs` is remembered for later states.
+    fn dominated(&mut self, gs: &GameState) -> bool {
+        let seen = self.seen.entry(gs.levels.clone()).or_default();
+        let covers = |a: &Seen, time: f64, points: f64, res_amt: &[f64]| {
+            a.time <= time
+                && a.points >= points
+                && a.res_amt.iter().zip(res_amt.iter()).all(|(x, y)| x >= y)
+        };
+        if seen
+            .iter()
+            .any(|s| covers(s, gs.time, gs.points, &gs.res_amt))
+        {
+            return true;
+        }
+        let new = Seen {
+            time: gs.time,
+            points: gs.points,
+            res_amt: gs.res_amt.clone(),
+        };
+        seen.retain(|s| !covers(&new, s.time, s.points, &s.res_amt));
+        seen.push(new);
+        false
+    }
+
+    /// The most points any plan could make, as far as the search knows.
+    pub fn upper_bound(&self) -> f64 {
+        self.best.max(self.open_bound)
+    }
+}
+
+/// Points at the end of the event if no more moves are made.
+fn final_points(gs: &GameState) -> f64 {
+    let mut end = GameState::new_from_game(gs.g);
+    end.copy_from(gs);
+    end.finish();
+    end.points
+}
+
+/// An upper bound on the points at the end of the event after at most `moves_left` more level
+/// ups from `gs`. The point rate is (producer points) * (1 + boost multipliers) / time factor,
+/// and each of the three is pushed as far as `moves_left` level ups could take it on its own,
+/// with no costs, as if they were all made right away. Offline time is taken to earn at the
+/// full rate, or faster if the game pays more than that for being away.
+pub fn bound(gs: &GameState, moves_left: usize) -> f64 {
+    let g = gs.g;
+    let mut producer_pts = 0.0;
+    let mut pts_gains = Vec::new();
+    let mut pt_mult = 0.0;
+    let mut mult_gains = Vec::new();
+    let mut time_fact = 1.0 + gs.commercial_mod;
+    let mut time_gains = Vec::new();
+    for (uidx, upg) in g.upgrades.iter().enumerate() {
+        let level = gs.levels[uidx];
+        let top = upg.costs().len().min(level + moves_left);
+        match upg {
+            Upgrade::Producer(prod) => {
+                let pts = |l: usize| prod.get_pt_rate(l, gs.prod2[uidx]);
+                producer_pts += pts(level);
+                pts_gains.push((level..=top).map(|l| pts(l) - pts(level)).collect());
+            }
+            Upgrade::Boost(boost) => {
+                let mult = |l: usize| if l < 1 { 0.0 } else { boost.pt_mult[l - 1] };
+                let time = |l: usize| if l < 1 { 0.0 } else { boost.time_mod[l - 1] };
+                pt_mult += mult(level);
+                time_fact += time(level);
+                mult_gains.push((level..=top).map(|l| mult(l) - mult(level)).collect());
+                time_gains.push((level..=top).map(|l| time(level) - time(l)).collect());
+            }
+        }
+    }
+    let time_fact = time_fact - best_gain(&time_gains, moves_left);
+    if time_fact <= 0.0 {
+        return f64::INFINITY;
+    }
+    let offline_rate = g.offline.as_ref().map_or(1.0, |off| off.rate.max(1.0));
+    let rate = (producer_pts + best_gain(&pts_gains, moves_left))
+        * (1.0 + pt_mult + best_gain(&mult_gains, moves_left))
+        / time_fact;
+    gs.points + rate.max(gs.pt_rate) * offline_rate * (g.event_time - gs.time)
+}
+
+/// The biggest total gain from at most `moves` level ups, where `gains[u][j]` is what `j` more
+/// levels of upgrade `u` gain.
+fn best_gain(gains: &[Vec<f64>], moves: usize) -> f64 {
+    let mut best = vec![0.0; moves + 1]; // best[m]: best gain using at most m moves
+    for upg_gains in gains {
+        let mut next = best.clone();
+        for m in 0..=moves {
+            for (j, gain) in upg_gains.iter().enumerate().take(m + 1) {
+                next[m] = f64::max(next[m], best[m - j] + gain);
+            }
+        }
+        best = next;
+    }
+    best[moves]
+}
+
+pub fn solve_main(
+    gs: GameState,
+    output_file: &str,
+    max_moves: usize,
+    budget_secs: f64,
+) -> Result<(), Box<dyn Error>> {
+    let g = gs.g;
+    println!(
+        "Searching plans of up to {} moves, upper bound {:.4e} {}",
+        max_moves,
+        bound(&gs, max_moves),
+        g.points_name
+    );
+    let started = Instant::now();
+    let solver = Solver::solve(&gs, max_moves, Duration::from_secs_f64(budget_secs));
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "{} states searched, {} cut off by the bound, {} dominated, in {:.1} s",
+        solver.nodes, solver.pruned_bound, solver.pruned_dominated, elapsed
+    );
+    if solver.timed_out {
+        let ub = solver.upper_bound();
+        println!(
+            "Gave up after {:.0} s. Best plan found: {:.4e} {}; no plan can beat {:.4e} (gap {:.2}%)",
+            budget_secs,
+            solver.best,
+            g.points_name,
+            ub,
+            100.0 * (ub - solver.best) / ub
+        );
+    } else {
+        println!(
+            "Optimal: {:.4e} {}, proven by exhausting the search",
+            solver.best, g.points_name
+        );
+    }
+    for mv in &solver.best_seq {
+        if let Move::LvlUp(lvlup) = mv {
+            println!(
+                "  {:>5} {} -> {}",
+                mv.to_string(g),
+                g.upgrades[lvlup.uidx].get_name(),
+                lvlup.level
+            );
+        }
+    }
+    if !output_file.is_empty() {
+        let mut file = File::create(output_file)?;
+        for mv in &solver.best_seq {
+            writeln!(&mut file, "{}", mv.to_string(g))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Offline;
+    use crate::tests::other_tower;
+
+    #[test]
+    fn test_best_gain() {
+        let gains = vec![vec![0.0, 1.0, 5.0], vec![0.0, 3.0]];
+        assert_eq!(best_gain(&gains, 0), 0.0);
+        assert_eq!(best_gain(&gains, 1), 3.0);
+        assert_eq!(best_gain(&gains, 2), 5.0);
+        assert_eq!(best_gain(&gains, 3), 8.0);
+    }
+
+    /// The most points any plan of at most `moves_left` level ups from `gs` makes, checking
+    /// that `bound` holds at every state on the way.
+    fn brute(gs: &GameState, moves_left: usize) -> f64 {
+        let mut best = final_points(gs);
+        if moves_left > 0 {
+            for (uidx, upg) in gs.g.upgrades.iter().enumerate() {
+                let level = gs.levels[uidx] + 1;
+                let mv = Move::LvlUp(LvlUp { uidx, level });
+                if level > upg.costs().len() || !upg_seq::ready(gs, &mv) {
+                    continue;
+                }
+                let mut child = GameState::new_from_game(gs.g);
+                child.copy_from(gs);
+                if upg_seq::play_move(&mut child, &mv) && child.time < gs.g.event_time {
+                    best = best.max(brute(&child, moves_left - 1));
+                }
+            }
+        }
+        assert!(bound(gs, moves_left) >= best);
+        best
+    }
+
+    #[test]
+    fn test_solve_small() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        let solver = Solver::solve(&gs, 4, Duration::from_secs(60));
+        assert!(!solver.timed_out);
+        let mut scratch = GameState::new_from_game(&g);
+        assert_eq!(
+            upg_seq::score(&gs, &mut scratch, &solver.best_seq),
+            solver.best
+        );
+
+        gs.update_rates();
+        assert_eq!(brute(&gs, 4), solver.best);
+    }
+
+    #[test]
+    fn test_solve_offline() {
+        let mut g = other_tower();
+        g.offline = Some(Offline {
+            after: 10.0 * 60.0,
+            max: 2.0 * 60.0 * 60.0,
+            rate: 0.5,
+        });
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        let solver = Solver::solve(&gs, 4, Duration::from_secs(60));
+        assert!(!solver.timed_out);
+        assert_eq!(solver.pruned_dominated, 0);
+        gs.update_rates();
+        assert_eq!(brute(&gs, 4), solver.best);
+    }
+}
@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::game::{Game, Move};
+use crate::game_state::GameState;
+use crate::upg_seq;
+
+/// Everything about one move of a played sequence. Resource vectors are indexed like
+/// `Game::res_names`, and rates are per second after the move was made.
+#[derive(Clone, Debug, Serialize)]
+pub struct TraceStep {
+    pub index: usize,
+    #[serde(rename = "move")]
+    pub mv: String,
+    pub upgrade: String,
+    pub level: usize,
+    pub time: f64,
+    pub wait: f64,
+    pub res_before: Vec<f64>,
+    pub res_after: Vec<f64>,
+    pub res_rate: Vec<f64>,
+    pub cost: Vec<i32>,
+    pub points: f64,
+    pub pt_rate: f64,
+}
+
+/// Plays a sequence the same way `upg_seq::score` does, yielding a `TraceStep` per move. Stops
+/// at the first move that can never be afforded.
+pub struct Trace<'a, 's> {
+    scratch: GameState<'a>,
+    seq: &'s [Move],
+    index: usize,
+}
+
+impl<'a, 's> Trace<'a, 's> {
+    pub fn new(gs: &GameState<'a>, seq: &'s [Move]) -> Trace<'a, 's> {
+        let mut scratch = GameState::new_from_game(gs.g);
+        scratch.copy_from(gs);
+        scratch.update_rates();
+        Trace {
+            scratch,
+            seq,
+            index: 0,
+        }
+    }
+
+    /// The state after the last move yielded.
+    pub fn state(&self) -> &GameState<'a> {
+        &self.scratch
+    }
+}
+
+impl Iterator for Trace<'_, '_> {
+    type Item = TraceStep;
+
+    fn next(&mut self) -> Option<TraceStep> {
+        let mv = *self.seq.get(self.index)?;
+        let gs = &mut self.scratch;
+        let g = gs.g;
+        let time_before = gs.time;
+        let mut res_before = gs.res_amt.clone();
+        let (upgrade, level, cost) = match &mv {
+            Move::LvlUp(lvlup) => {
+                gs.advance_time(gs.lvlup_wait(lvlup.uidx, 0.0)?);
+                res_before.copy_from_slice(&gs.res_amt);
+                gs.level_up(lvlup.uidx);
+                let upg = &g.upgrades[lvlup.uidx];
+                (
+                    upg.get_name(),
+                    lvlup.level,
+                    upg.costs()[lvlup.level - 1].clone(),
+                )
+            }
+            Move::Switch(sw) => {
+                gs.advance_time(gs.switch_wait());
+                res_before.copy_from_slice(&gs.res_amt);
+                gs.change_prod(sw);
+                (
+                    g.upgrades[sw.uidx].get_name(),
+                    gs.levels[sw.uidx],
+                    vec![0; g.nres],
+                )
+            }
+        };
+        self.index += 1;
+        Some(TraceStep {
+            index: self.index - 1,
+            mv: mv.to_string(g),
+            upgrade: upgrade.to_string(),
+            level,
+            time: gs.time,
+            wait: gs.time - time_before,
+            res_before,
+            res_after: gs.res_amt.clone(),
+            res_rate: gs.res_rate.clone(),
+            cost,
+            points: gs.points,
+            pt_rate: gs.pt_rate,
+        })
+    }
+}
+
+pub fn trace_main(
+    gs: GameState,
+    moves_file: &str,
+    output_file: &str,
+    format: &str,
+    lenient: bool,
+) -> Result<(), Box<dyn Error>> {
+    let seq = upg_seq::load_sequence(moves_file, &gs, lenient)?;
+    let steps: Vec<TraceStep> = Trace::new(&gs, &seq).collect();
+    if steps.len() < seq.len() {
+        println!(
+            "Move #{} can't be afforded, tracing stops there",
+            steps.len()
+        );
+    }
+
+    let mut out: Box<dyn Write> = if !output_file.is_empty() {
+        Box::new(File::create(output_file)?)
+    } else {
+        Box::new(std::io::stdout())
+    };
+    match format {
+        "csv" => write_csv(&mut out, gs.g, &steps)?,
+        "json" => write_json(&mut out, gs.g, &steps)?,
+        _ => return Err(format!("unknown trace format {:?}, use csv or json", format).into()),
+    }
+    Ok(())
+}
+
+/// One row per step, with a column per resource for each resource vector.
+pub fn write_csv(
+    out: &mut dyn Write,
+    g: &Game,
+    steps: &[TraceStep],
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(out);
+    let mut header: Vec<String> = ["index", "move", "upgrade", "level", "time", "wait"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    for col in &["before", "after", "rate", "cost"] {
+        for res in &g.res_names {
+            header.push(format!("{} {}", res, col));
+        }
+    }
+    header.push(g.points_name.clone());
+    header.push(format!("{} rate", g.points_name));
+    wtr.write_record(&header)?;
+
+    for st in steps {
+        let mut row = vec![
+            st.index.to_string(),
+            st.mv.clone(),
+            st.upgrade.clone(),
+            st.level.to_string(),
+            st.time.to_string(),
+            st.wait.to_string(),
+        ];
+        for vals in &[&st.res_before, &st.res_after, &st.res_rate] {
+            row.extend(vals.iter().map(|v| v.to_string()));
+        }
+        row.extend(st.cost.iter().map(|v| v.to_string()));
+        row.push(st.points.to_string());
+        row.push(st.pt_rate.to_string());
+        wtr.write_record(&row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct TraceDoc<'t> {
+    game: &'t str,
+    resources: &'t [String],
+    points_name: &'t str,
+    steps: &'t [TraceStep],
+}
+
+pub fn write_json(
+    out: &mut dyn Write,
+    g: &Game,
+    steps: &[TraceStep],
+) -> Result<(), Box<dyn Error>> {
+    let doc = TraceDoc {
+        game: &g.name,
+        resources: &g.res_names,
+        points_name: &g.points_name,
+        steps,
+    };
+    serde_json::to_writer_pretty(&mut *out, &doc)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{opening, other_tower};
+
+    #[test]
+    fn test_trace_matches_timeline() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.gem_boost = 1;
+        let seq = opening();
+
+        let mut scratch = GameState::new_from_game(&g);
+        let timed = upg_seq::timeline(&gs, &mut scratch, &seq);
+        let steps: Vec<TraceStep> = Trace::new(&gs, &seq).collect();
+        assert_eq!(steps.len(), timed.len());
+        for (st, tm) in steps.iter().zip(timed.iter()) {
+            assert_eq!(st.time, tm.time);
+        }
+        let st = &steps[0];
+        for ires in 0..g.nres {
+            let paid = st.res_before[ires] - st.cost[ires] as f64;
+            assert!((paid - st.res_after[ires]).abs() < 1e-9);
+        }
+    }
+}
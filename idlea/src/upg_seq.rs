@@ -1,15 +1,41 @@
+use std::error::Error;
 use std::fs::File;
 use hashbrown::{HashMap, HashSet};
+use rand::Rng;
 // use num_iter;
 // use streaming_iterator::StreamingIterator;
 
 use crate::game::{Game, Upgrade, Move, LvlUp, Switch};
 use crate::game_state::GameState;
+use crate::say;
 
-pub fn load_sequence(csv_file: &str, gs: &GameState) -> Vec<Move> {
-    let file = File::open(csv_file).unwrap();
+/// A problem found while loading a move file, with the line it was found on.
+#[derive(Debug)]
+pub struct SeqError {
+    pub line: u64,
+    pub msg: String,
+}
+
+impl std::fmt::Display for SeqError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl Error for SeqError {}
+
+/// Reads a move file, either a plain list of upgrade indices (as written by `-o`) or a
+/// replay .csv with an "upg #" column. Any move that can't be made in order from `gs` is an
+/// error, unless `lenient` is set, in which case it is reported and skipped.
+pub fn load_sequence(
+    csv_file: &str,
+    gs: &GameState,
+    lenient: bool,
+) -> Result<Vec<Move>, Box<dyn Error>> {
+    let file = File::open(csv_file)?;
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false)
+        .flexible(true)
         .from_reader(file);
     let mut recs = rdr.records();
 
@@ -25,103 +51,283 @@ pub fn load_sequence(csv_file: &str, gs: &GameState) -> Vec<Move> {
         }
     }
     for (k, v) in switch_moves.iter() {
-        println!("{}: {}~{}", k, v.uidx, v.iprod);
+        say!("{}: {}~{}", k, v.uidx, v.iprod);
     }
 
     let mut moves = Vec::new();
     let mut levels = gs.levels.to_vec();
+    let mut done = initial_done(gs);
 
-    let header = recs.next().unwrap().unwrap();
+    let header = match recs.next() {
+        Some(header) => header?,
+        None => return Ok(moves),
+    };
     let mut upg_idx_col: usize = 0;
+    let mut first_row = None;
     if header.len() == 1 {
-        let uidx: usize = header[0].parse().unwrap();
-        levels[uidx] += 1;
-        moves.push(Move::LvlUp(LvlUp {
-            uidx,
-            level: levels[uidx],
-        }));
+        first_row = Some(header);
     } else {
-        upg_idx_col = header.iter().position(|s| s == "upg #").unwrap();
+        upg_idx_col = header
+            .iter()
+            .position(|s| s == "upg #")
+            .ok_or_else(|| SeqError {
+                line: line_of(&header),
+                msg: "header has no \"upg #\" column".to_string(),
+            })?;
     }
 
-    for row in recs {
-        let svalue = &row.as_ref().unwrap()[upg_idx_col];
-        if let Some(sw) = switch_moves.get(svalue) {
-            moves.push(Move::Switch(*sw));
-            continue;
-        }
-        let uidxr: Result<i32, _> = svalue.parse();
-        match uidxr {
-            Ok(uidx) => {
-                if uidx >= 0 {
-                    levels[uidx as usize] += 1;
-                    moves.push(Move::LvlUp(LvlUp {
-                        uidx: uidx as usize,
-                        level: levels[uidx as usize],
-                    }));
-                } else {
-                    println!("Removing final move {}", uidx);
+    for row in first_row.into_iter().map(Ok).chain(recs) {
+        let row = row?;
+        let line = line_of(&row);
+        let svalue = match row.get(upg_idx_col) {
+            Some(svalue) => svalue.trim(),
+            None => "",
+        };
+        let rslt = parse_move(svalue, gs, &switch_moves, &levels, &done);
+        let mv = match rslt {
+            Ok(Some(mv)) => mv,
+            Ok(None) => {
+                say!("Removing final move {}", svalue);
+                continue;
+            }
+            Err(msg) => {
+                let err = SeqError { line, msg };
+                if lenient {
+                    say!("Skipping {}", err);
+                    continue;
                 }
+                return Err(Box::new(err));
             }
-            Err(_) => {
-                println!(
-                    "Cannot parse {:?} in column {} as usize",
-                    svalue, upg_idx_col
-                );
+        };
+        if let Move::LvlUp(lvlup) = mv {
+            levels[lvlup.uidx] = lvlup.level;
+        }
+        done.insert(mv);
+        moves.push(mv);
+    }
+
+    Ok(moves)
+}
+
+/// The moves already made in `gs`, as far as prerequisites are concerned: every level up to the
+/// current one, and a switch to the current production.
+pub fn initial_done(gs: &GameState) -> HashSet<Move> {
+    let mut done = HashSet::new();
+    for (uidx, &level) in gs.levels.iter().enumerate() {
+        for lvl in 1..=level {
+            done.insert(Move::LvlUp(LvlUp { uidx, level: lvl }));
+        }
+        if let Upgrade::Producer(prod) = &gs.g.upgrades[uidx] {
+            if !prod.prod_names.0.is_empty() {
+                done.insert(Move::Switch(Switch {
+                    uidx,
+                    iprod: gs.prod2[uidx] as usize,
+                }));
             }
+        }
+    }
+    done
+}
+
+/// Whether the level ups `mv` needs first have been made in `gs`.
+pub fn ready(gs: &GameState, mv: &Move) -> bool {
+    match gs.g.prereqs.get(mv) {
+        Some(prereqs) => prereqs.iter().all(|p| match p {
+            Move::LvlUp(lvlup) => gs.levels[lvlup.uidx] >= lvlup.level,
+            Move::Switch(_) => true,
+        }),
+        None => true,
+    }
+}
+
+/// Reorders `seq` so that no move comes before its prerequisites. A move that isn't ready
+/// yet waits, and is made as soon as the last of its prerequisites is. Moves whose
+/// prerequisites aren't in `seq` at all end up at the end.
+pub fn repair(seq: &[Move], gs: &GameState) -> Vec<Move> {
+    let g = gs.g;
+    let mut done = initial_done(gs);
+    let ready = |mv: &Move, done: &HashSet<Move>| match g.prereqs.get(mv) {
+        Some(prereqs) => prereqs.iter().all(|p| done.contains(p)),
+        None => true,
+    };
+    let mut fixed = Vec::with_capacity(seq.len());
+    let mut waiting = Vec::new();
+    for mv in seq {
+        if !ready(mv, &done) {
+            waiting.push(*mv);
+            continue;
+        }
+        fixed.push(*mv);
+        done.insert(*mv);
+        while let Some(i) = waiting.iter().position(|w| ready(w, &done)) {
+            let w = waiting.remove(i);
+            fixed.push(w);
+            done.insert(w);
+        }
+    }
+    fixed.extend(waiting);
+    fixed
+}
+
+fn line_of(rec: &csv::StringRecord) -> u64 {
+    rec.position().map_or(0, |pos| pos.line())
+}
+
+/// Works out which move a single entry of a move file stands for, given the levels and moves
+/// already made. Negative upgrade indices mark the end of a replay and give `Ok(None)`.
+fn parse_move(
+    svalue: &str,
+    gs: &GameState,
+    switch_moves: &HashMap<String, Switch>,
+    levels: &[usize],
+    done: &HashSet<Move>,
+) -> Result<Option<Move>, String> {
+    let g = gs.g;
+    let mv = if let Some(sw) = switch_moves.get(svalue) {
+        Move::Switch(*sw)
+    } else {
+        let digits = svalue
+            .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .unwrap_or(svalue.len());
+        let uidx: i32 = match svalue[..digits].parse() {
+            Ok(uidx) => uidx,
+            Err(_) => return Err(format!("cannot parse {:?} as a move", svalue)),
         };
+        if uidx < 0 {
+            return Ok(None);
+        }
+        let uidx = uidx as usize;
+        if uidx >= g.upgrades.len() {
+            return Err(format!(
+                "unknown upgrade {:?}, game has {} upgrades",
+                svalue,
+                g.upgrades.len()
+            ));
+        }
+        let upg = &g.upgrades[uidx];
+        if digits < svalue.len() {
+            return Err(format!(
+                "{:?}: {} has no production switch {:?}",
+                svalue,
+                upg.get_name(),
+                &svalue[digits..]
+            ));
+        }
+        let level = levels[uidx] + 1;
+        if level > upg.costs().len() {
+            return Err(format!(
+                "{} can't go to level {}, max is {}",
+                upg.get_name(),
+                level,
+                upg.costs().len()
+            ));
+        }
+        Move::LvlUp(LvlUp { uidx, level })
+    };
+
+    if let Some(prereqs) = g.prereqs.get(&mv) {
+        if let Some(missing) = prereqs.iter().find(|p| !done.contains(*p)) {
+            return Err(format!(
+                "{} comes before its prerequisite {}",
+                describe_move(&mv, g),
+                describe_move(missing, g)
+            ));
+        }
     }
+    Ok(Some(mv))
+}
 
-    moves
+fn describe_move(mv: &Move, g: &Game) -> String {
+    match mv {
+        Move::LvlUp(lvlup) => {
+            format!(
+                "{} level {}",
+                g.upgrades[lvlup.uidx].get_name(),
+                lvlup.level
+            )
+        }
+        Move::Switch(sw) => format!("switch {}", sw.to_string(g)),
+    }
 }
 
 pub fn score(gs: &GameState, scratch: &mut GameState, seq: &[Move]) -> f64 {
     scratch.copy_from(gs);
     scratch.update_rates();
+    score_rest(scratch, seq)
+}
+
+/// Like `score`, but carries on from wherever `scratch` is instead of from a starting state.
+pub fn score_rest(scratch: &mut GameState, seq: &[Move]) -> f64 {
     for mv in seq {
         // println!("{}: t={}, pt_rate={} points={}",
         //     mv, scratch.time, scratch.pt_rate, scratch.points);
-        match mv {
-            Move::LvlUp(lvlup) => {
-                let ttl = scratch.time_till_lvlup(lvlup.uidx);
-                match ttl {
-                    Some(t) => {
-                        let t = t.ceil() + 1.0;
-                        scratch.advance_time(t);
-                        scratch.level_up(lvlup.uidx);
-                    }
-                    None => break,
-                }
-            }
-            Move::Switch(sw) => {
-                scratch.change_prod(sw);
-            }
+        if !play_move(scratch, mv) {
+            break;
         }
     }
     scratch.finish();
     scratch.points
 }
 
+/// Waits for and makes one move, or returns false if it can never be afforded.
+pub fn play_move(scratch: &mut GameState, mv: &Move) -> bool {
+    match mv {
+        Move::LvlUp(lvlup) => match scratch.lvlup_wait(lvlup.uidx, 0.0) {
+            Some(t) => {
+                scratch.advance_time(t);
+                scratch.level_up(lvlup.uidx);
+            }
+            None => return false,
+        },
+        Move::Switch(sw) => {
+            scratch.advance_time(scratch.switch_wait());
+            scratch.change_prod(sw);
+        }
+    }
+    true
+}
+
 pub fn score_spare(gs: &GameState, scratch: &mut GameState, seq: &[Move]) -> f64 {
+    score_spare_to(gs, scratch, seq, gs.g.goal)
+}
+
+/// Like `score_spare`, but for reaching `goal` points instead of the game's own goal.
+pub fn score_spare_to(gs: &GameState, scratch: &mut GameState, seq: &[Move], goal: f64) -> f64 {
     scratch.copy_from(gs);
     scratch.update_rates();
-    for mv in seq {
+    score_spare_rest(scratch, seq, goal)
+}
+
+/// Like `score_spare_to`, but carries on from wherever `scratch` is. The points so far must not
+/// be past `goal` yet.
+pub fn score_spare_rest(scratch: &mut GameState, seq: &[Move], goal: f64) -> f64 {
+    score_spare_delayed(scratch, seq, goal, &[])
+}
+
+/// Like `score_spare_rest`, but the player makes move `i` `delays[i]` seconds after it
+/// becomes affordable. Moves past the end of `delays` aren't delayed.
+pub fn score_spare_delayed(
+    scratch: &mut GameState,
+    seq: &[Move],
+    goal: f64,
+    delays: &[f64],
+) -> f64 {
+    for (i, mv) in seq.iter().enumerate() {
         // println!("{}: t={}, pt_rate={} points={}",
         //     mv, scratch.time, scratch.pt_rate, scratch.points);
+        let delay = delays.get(i).copied().unwrap_or(0.0);
         match mv {
             Move::LvlUp(lvlup) => {
-                let ttl = scratch.time_till_lvlup(lvlup.uidx);
-                match ttl {
+                match scratch.lvlup_wait(lvlup.uidx, delay) {
                     Some(t) => {
-                        let t = t.ceil() + 1.0;
-
-                        if scratch.points + scratch.pt_rate * t > scratch.g.goal
+                        if scratch.points + scratch.pt_rate * scratch.credited(t) > goal
                             && scratch.pt_rate > 0.0
                         {
-                            let dt_win = (scratch.g.goal - scratch.points) / scratch.pt_rate;
+                            let credit = (goal - scratch.points) / scratch.pt_rate;
+                            let dt_win = scratch.time_to_credit(t, credit);
                             let spare = scratch.g.event_time - (scratch.time + dt_win);
                             // println!("Early win by {} seconds", spare);
-                            return spare / 60.0 / 60.0;
+                            return spare / 60.0 / 60.0 - checkin_penalty(scratch);
                         }
 
                         scratch.advance_time(t);
@@ -131,6 +337,10 @@ pub fn score_spare(gs: &GameState, scratch: &mut GameState, seq: &[Move]) -> f64
                 }
             }
             Move::Switch(sw) => {
+                if delay > 0.0 {
+                    scratch.advance_time(delay);
+                }
+                scratch.advance_time(scratch.switch_wait());
                 scratch.change_prod(sw);
             }
         }
@@ -139,10 +349,80 @@ pub fn score_spare(gs: &GameState, scratch: &mut GameState, seq: &[Move]) -> f64
     let spare = if scratch.pt_rate == 0.0 {
         -1.0e30
     } else {
-        -(scratch.g.goal - scratch.points) / scratch.pt_rate
+        -(goal - scratch.points) / scratch.pt_rate
     };
-    // println!("goal={} pts={} rate={} spare={}", scratch.g.goal, scratch.points, scratch.pt_rate, spare);
-    spare / 60. / 60.
+    // println!("goal={} pts={} rate={} spare={}", goal, scratch.points, scratch.pt_rate, spare);
+    spare / 60. / 60. - checkin_penalty(scratch)
+}
+
+/// Hours taken off a spare-time score for the check-ins made so far.
+fn checkin_penalty(gs: &GameState) -> f64 {
+    match &gs.g.schedule {
+        Some(sched) => sched.checkin_penalty * gs.checkins as f64,
+        None => 0.0,
+    }
+}
+
+/// Points at `horizon` seconds into the event, ignoring any moves that come later.
+pub fn score_at(gs: &GameState, scratch: &mut GameState, seq: &[Move], horizon: f64) -> f64 {
+    scratch.copy_from(gs);
+    scratch.update_rates();
+    score_at_rest(scratch, seq, horizon)
+}
+
+/// Like `score_at`, but carries on from wherever `scratch` is, which must not be past `horizon`.
+pub fn score_at_rest(scratch: &mut GameState, seq: &[Move], horizon: f64) -> f64 {
+    for mv in seq {
+        match mv {
+            Move::LvlUp(lvlup) => match scratch.lvlup_wait(lvlup.uidx, 0.0) {
+                Some(t) => {
+                    if scratch.time + t > horizon {
+                        break;
+                    }
+                    scratch.advance_time(t);
+                    scratch.level_up(lvlup.uidx);
+                }
+                None => break,
+            },
+            Move::Switch(sw) => {
+                let t = scratch.switch_wait();
+                if scratch.time + t > horizon {
+                    break;
+                }
+                scratch.advance_time(t);
+                scratch.change_prod(sw);
+            }
+        }
+    }
+    if horizon > scratch.time {
+        scratch.advance_time(horizon - scratch.time);
+    }
+    scratch.points
+}
+
+/// When a move is made while playing a sequence, in seconds since the event started.
+#[derive(Clone, Copy, Debug)]
+pub struct TimedMove {
+    pub time: f64,
+    pub mv: Move,
+}
+
+/// Plays `seq` the same way `score` does and returns the time of each move made. Moves after
+/// the first one that can never be afforded are left out.
+pub fn timeline(gs: &GameState, scratch: &mut GameState, seq: &[Move]) -> Vec<TimedMove> {
+    scratch.copy_from(gs);
+    scratch.update_rates();
+    let mut timed = Vec::new();
+    for mv in seq {
+        if !play_move(scratch, mv) {
+            break;
+        }
+        timed.push(TimedMove {
+            time: scratch.time,
+            mv: *mv,
+        });
+    }
+    timed
 }
 
 pub trait VarIter {
@@ -380,3 +660,706 @@ impl VarIter for VariationsPushy<'_> {
         }
     }
 }
+
+/// Producers whose production can be switched, by upgrade index.
+pub fn switchable(g: &Game) -> Vec<usize> {
+    g.upgrades
+        .iter()
+        .enumerate()
+        .filter_map(|(uidx, upg)| match upg {
+            Upgrade::Producer(prod) if !prod.prod_names.0.is_empty() => Some(uidx),
+            _ => None,
+        })
+        .collect()
+}
+
+const MAX_BLOCK: usize = 6; // longest run of moves `Edits::blocks` moves together
+const MAX_REVERSE: usize = 6; // longest run of moves `Edits::reversals` turns around
+
+/// Which kinds of variation the optimizers try, from `--ops`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Operators {
+    pub slide: bool, // one move earlier or later, as `Variations` (or `VariationsPushy`)
+    pub switches: bool, // add or remove a pair of production switches
+    pub swap: bool,  // two moves trade places
+    pub block: bool, // a run of moves goes earlier or later together
+    pub reverse: bool, // a run of moves in reverse order
+    pub trailing: bool, // add or drop moves at the end of what can be afforded
+}
+
+impl Operators {
+    pub const SLIDE_ONLY: Operators = Operators {
+        slide: true,
+        switches: false,
+        swap: false,
+        block: false,
+        reverse: false,
+        trailing: false,
+    };
+
+    /// Parses a comma-separated list of operator names.
+    pub fn from_arg(arg: &str) -> Result<Operators, String> {
+        let mut ops = Operators {
+            slide: false,
+            ..Operators::SLIDE_ONLY
+        };
+        for name in arg.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            match name {
+                "slide" => ops.slide = true,
+                "switches" => ops.switches = true,
+                "swap" => ops.swap = true,
+                "block" => ops.block = true,
+                "reverse" => ops.reverse = true,
+                "trailing" => ops.trailing = true,
+                _ => {
+                    return Err(format!(
+                    "unknown operator {:?}, use slide, switches, swap, block, reverse or trailing",
+                    name
+                ))
+                }
+            }
+        }
+        Ok(ops)
+    }
+}
+
+/// Whether `mv` has to come after `other`.
+fn needs(g: &Game, mv: &Move, other: &Move) -> bool {
+    g.prereqs
+        .get(mv)
+        .is_some_and(|prereqs| prereqs.contains(other))
+}
+
+/// Whether the moves in `later` can all go before those in `earlier`: none of them needs one
+/// of `earlier`.
+fn can_pass(g: &Game, earlier: &[Move], later: &[Move]) -> bool {
+    later
+        .iter()
+        .all(|mv| !earlier.iter().any(|other| needs(g, mv, other)))
+}
+
+/// A change to a sequence, with indices into the original.
+enum Edit {
+    /// Put `first` just before `at` and `second` just before `back`.
+    InsertPair {
+        at: usize,
+        first: Move,
+        back: usize,
+        second: Move,
+    },
+    Insert(usize, Move),
+    Remove(usize),
+    RemovePair(usize, usize),
+    Swap(usize, usize),
+    /// Take out the `len` moves from `from` and put them back starting at `to`.
+    Block {
+        from: usize,
+        len: usize,
+        to: usize,
+    },
+    /// Reverse the moves from the first index to the second, inclusive.
+    Reverse(usize, usize),
+}
+
+impl Edit {
+    fn apply(&self, orig: &[Move], seq: &mut Vec<Move>) {
+        seq.clear();
+        match *self {
+            Edit::InsertPair {
+                at,
+                first,
+                back,
+                second,
+            } => {
+                seq.extend_from_slice(&orig[..at]);
+                seq.push(first);
+                seq.extend_from_slice(&orig[at..back]);
+                seq.push(second);
+                seq.extend_from_slice(&orig[back..]);
+            }
+            Edit::Insert(at, mv) => {
+                seq.extend_from_slice(&orig[..at]);
+                seq.push(mv);
+                seq.extend_from_slice(&orig[at..]);
+            }
+            Edit::Remove(at) => {
+                seq.extend_from_slice(&orig[..at]);
+                seq.extend_from_slice(&orig[at + 1..]);
+            }
+            Edit::RemovePair(a, b) => {
+                seq.extend_from_slice(&orig[..a]);
+                seq.extend_from_slice(&orig[a + 1..b]);
+                seq.extend_from_slice(&orig[b + 1..]);
+            }
+            Edit::Swap(i, j) => {
+                seq.extend_from_slice(orig);
+                seq.swap(i, j);
+            }
+            Edit::Block { from, len, to } => {
+                seq.extend_from_slice(&orig[..from]);
+                seq.extend_from_slice(&orig[from + len..]);
+                let tail = seq.split_off(to);
+                seq.extend_from_slice(&orig[from..from + len]);
+                seq.extend_from_slice(&tail);
+            }
+            Edit::Reverse(i, j) => {
+                seq.extend_from_slice(orig);
+                seq[i..=j].reverse();
+            }
+        }
+    }
+}
+
+/// Variations made by a list of edits to the original sequence, worked out up front. Each
+/// constructor only lists edits that keep every move after its prerequisites.
+pub struct Edits<'a> {
+    orig_seq: &'a [Move],
+    seq: Vec<Move>,
+    edits: std::vec::IntoIter<Edit>,
+}
+
+impl VarIter for Edits<'_> {
+    fn next(&mut self) -> Option<&[Move]> {
+        self.edits.next()?.apply(self.orig_seq, &mut self.seq);
+        Some(&self.seq)
+    }
+}
+
+impl Edits<'_> {
+    fn new(seq: &[Move], edits: Vec<Edit>) -> Edits<'_> {
+        Edits {
+            orig_seq: seq,
+            seq: Vec::with_capacity(seq.len() + 2),
+            edits: edits.into_iter(),
+        }
+    }
+
+    /// Adds or removes a pair of production switches, so the optimizer can pick how many
+    /// switches each producer gets. A pair can go in anywhere after the producer's first
+    /// level, with the switch back 1, 2, 4, ... moves later or just before its next switch, so
+    /// the switches keep alternating. Any two switches of a producer in a row can come out
+    /// together.
+    pub fn switch_pairs<'a>(seq: &'a [Move], gs: &GameState) -> Edits<'a> {
+        let mut edits = Vec::new();
+        for uidx in switchable(gs.g) {
+            let is_switch = |mv: &Move| matches!(mv, Move::Switch(sw) if sw.uidx == uidx);
+            let switch_at: Vec<usize> = (0..seq.len()).filter(|&i| is_switch(&seq[i])).collect();
+            let mut iprod = gs.prod2[uidx] as usize;
+            let mut unlocked = gs.levels[uidx] >= 1;
+            let mut inext = 0; // first index in switch_at at or after `at`
+            for at in 0..=seq.len() {
+                if at > 0 {
+                    match seq[at - 1] {
+                        Move::LvlUp(lvlup) if lvlup.uidx == uidx => unlocked = true,
+                        Move::Switch(sw) if sw.uidx == uidx => {
+                            iprod = sw.iprod;
+                            inext += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                if !unlocked {
+                    continue;
+                }
+                let next = switch_at.get(inext).copied().unwrap_or(seq.len());
+                let mut span = 1;
+                while at + span <= next {
+                    let back = if at + 2 * span > next {
+                        next
+                    } else {
+                        at + span
+                    };
+                    edits.push(Edit::InsertPair {
+                        at,
+                        first: Move::Switch(Switch {
+                            uidx,
+                            iprod: 1 - iprod,
+                        }),
+                        back,
+                        second: Move::Switch(Switch { uidx, iprod }),
+                    });
+                    if back == next {
+                        break;
+                    }
+                    span *= 2;
+                }
+            }
+            for pair in switch_at.windows(2) {
+                edits.push(Edit::RemovePair(pair[0], pair[1]));
+            }
+        }
+        Edits::new(seq, edits)
+    }
+
+    /// Swaps two moves that aren't next to each other (`Variations` does those).
+    pub fn swaps<'a>(seq: &'a [Move], g: &Game) -> Edits<'a> {
+        let mut edits = Vec::new();
+        for i in 0..seq.len() {
+            for j in i + 2..seq.len() {
+                // The move at i goes after everything up to j, so nothing there may need it.
+                if needs(g, &seq[j - 1], &seq[i]) {
+                    break;
+                }
+                if !needs(g, &seq[j], &seq[i]) && can_pass(g, &seq[i..j], &seq[j..=j]) {
+                    edits.push(Edit::Swap(i, j));
+                }
+            }
+        }
+        Edits::new(seq, edits)
+    }
+
+    /// Moves runs of 2 to `MAX_BLOCK` moves earlier or later, as far as prerequisites allow.
+    pub fn blocks<'a>(seq: &'a [Move], g: &Game) -> Edits<'a> {
+        let mut edits = Vec::new();
+        for from in 0..seq.len() {
+            for len in 2..=MAX_BLOCK.min(seq.len() - from) {
+                let block = &seq[from..from + len];
+                for to in (0..from).rev() {
+                    if !can_pass(g, &seq[to..=to], block) {
+                        break;
+                    }
+                    edits.push(Edit::Block { from, len, to });
+                }
+                for end in from + len..seq.len() {
+                    if !can_pass(g, block, &seq[end..=end]) {
+                        break;
+                    }
+                    edits.push(Edit::Block {
+                        from,
+                        len,
+                        to: end + 1 - len,
+                    });
+                }
+            }
+        }
+        Edits::new(seq, edits)
+    }
+
+    /// Reverses runs of 3 to `MAX_REVERSE` moves where none of them needs another.
+    pub fn reversals<'a>(seq: &'a [Move], g: &Game) -> Edits<'a> {
+        let mut edits = Vec::new();
+        for i in 0..seq.len() {
+            for j in i + 1..seq.len().min(i + MAX_REVERSE) {
+                if !can_pass(g, &seq[i..j], &seq[j..=j]) {
+                    break;
+                }
+                if j >= i + 2 {
+                    edits.push(Edit::Reverse(i, j));
+                }
+            }
+        }
+        Edits::new(seq, edits)
+    }
+
+    /// Changes the end of the sequence, from the first move that can't be made before the event
+    /// ends: any of those moves that nothing later needs can be dropped, and the next level of
+    /// any upgrade that isn't in the sequence at all can go in where they start.
+    pub fn trailing<'a>(seq: &'a [Move], gs: &GameState) -> Edits<'a> {
+        let mut scratch = GameState::new_from_game(gs.g);
+        scratch.copy_from(gs);
+        scratch.update_rates();
+        let mut start = seq.len();
+        for (i, mv) in seq.iter().enumerate() {
+            if !play_move(&mut scratch, mv) || scratch.time >= gs.g.event_time {
+                start = i;
+                break;
+            }
+        }
+        let mut edits = Vec::new();
+        for i in start..seq.len() {
+            if !seq[i + 1..].iter().any(|later| needs(gs.g, later, &seq[i])) {
+                edits.push(Edit::Remove(i));
+            }
+        }
+        for (uidx, upg) in gs.g.upgrades.iter().enumerate() {
+            let level = scratch.levels[uidx] + 1;
+            let mv = Move::LvlUp(LvlUp { uidx, level });
+            if level <= upg.costs().len() && !seq.contains(&mv) && ready(&scratch, &mv) {
+                edits.push(Edit::Insert(start, mv));
+            }
+        }
+        Edits::new(seq, edits)
+    }
+}
+
+/// All of one set of variations, then all of another.
+pub struct Chain<'a> {
+    first: Box<dyn VarIter + 'a>,
+    second: Box<dyn VarIter + 'a>,
+    first_done: bool,
+}
+
+impl VarIter for Chain<'_> {
+    fn next(&mut self) -> Option<&[Move]> {
+        if !self.first_done {
+            if let Some(seq) = self.first.next() {
+                return Some(seq);
+            }
+            self.first_done = true;
+        }
+        self.second.next()
+    }
+}
+
+/// The variations `ops` make of `seq`, one operator after another. `pushy` slides take their
+/// prerequisites along.
+pub fn variations<'a>(
+    seq: &'a [Move],
+    gs: &GameState<'a>,
+    pushy: bool,
+    ops: Operators,
+) -> Box<dyn VarIter + 'a> {
+    let g = gs.g;
+    let mut all: Vec<Box<dyn VarIter + 'a>> = Vec::new();
+    if ops.slide && pushy {
+        all.push(Box::from(VariationsPushy::try_seqs(seq, g)));
+    } else if ops.slide {
+        all.push(Box::from(Variations::try_seqs(seq, g)));
+    }
+    if ops.switches {
+        all.push(Box::from(Edits::switch_pairs(seq, gs)));
+    }
+    if ops.swap {
+        all.push(Box::from(Edits::swaps(seq, g)));
+    }
+    if ops.block {
+        all.push(Box::from(Edits::blocks(seq, g)));
+    }
+    if ops.reverse {
+        all.push(Box::from(Edits::reversals(seq, g)));
+    }
+    if ops.trailing {
+        all.push(Box::from(Edits::trailing(seq, gs)));
+    }
+    let mut chained: Box<dyn VarIter + 'a> = all
+        .pop()
+        .unwrap_or_else(|| Box::from(Edits::new(seq, Vec::new())));
+    while let Some(first) = all.pop() {
+        chained = Box::from(Chain {
+            first,
+            second: chained,
+            first_done: false,
+        });
+    }
+    chained
+}
+
+const RANDOM_TRIES: usize = 100; // draws `random_variation` makes before giving up
+
+impl Edits<'_> {
+    /// One of the edits, at random, or None if there aren't any.
+    fn pick<R: Rng>(&self, rng: &mut R) -> Option<Vec<Move>> {
+        let edits = self.edits.as_slice();
+        if edits.is_empty() {
+            return None;
+        }
+        let mut seq = Vec::with_capacity(self.orig_seq.len() + 2);
+        edits[rng.gen_range(0, edits.len())].apply(self.orig_seq, &mut seq);
+        Some(seq)
+    }
+}
+
+/// Moves `seq[from]` to where `seq[to]` is, taking along the moves it passes that it needs
+/// (going earlier) or that need it (going later), as `VariationsPushy` does. Also returns
+/// how many moves went.
+fn slide(seq: &[Move], g: &Game, from: usize, to: usize) -> (Vec<Move>, usize) {
+    let mut taken = vec![false; seq.len()];
+    taken[from] = true;
+    let mut count = 1;
+    let mut new_seq = Vec::with_capacity(seq.len());
+    if to < from {
+        for k in (to..from).rev() {
+            if (k + 1..=from).any(|t| taken[t] && needs(g, &seq[t], &seq[k])) {
+                taken[k] = true;
+                count += 1;
+            }
+        }
+        new_seq.extend_from_slice(&seq[..to]);
+        new_seq.extend((to..=from).filter(|&k| taken[k]).map(|k| seq[k]));
+        new_seq.extend((to..=from).filter(|&k| !taken[k]).map(|k| seq[k]));
+        new_seq.extend_from_slice(&seq[from + 1..]);
+    } else {
+        for k in from + 1..=to {
+            if (from..k).any(|t| taken[t] && needs(g, &seq[k], &seq[t])) {
+                taken[k] = true;
+                count += 1;
+            }
+        }
+        new_seq.extend_from_slice(&seq[..from]);
+        new_seq.extend((from..=to).filter(|&k| !taken[k]).map(|k| seq[k]));
+        new_seq.extend((from..=to).filter(|&k| taken[k]).map(|k| seq[k]));
+        new_seq.extend_from_slice(&seq[to + 1..]);
+    }
+    (new_seq, count)
+}
+
+/// One of the variations `variations` would make, drawn at random without listing them all:
+/// picks an operator, then where to apply it, and draws again if that would put a move
+/// before its prerequisites. None if `RANDOM_TRIES` draws found nothing.
+pub fn random_variation<R: Rng>(
+    seq: &[Move],
+    gs: &GameState,
+    pushy: bool,
+    ops: Operators,
+    rng: &mut R,
+) -> Option<Vec<Move>> {
+    let g = gs.g;
+    let n = seq.len();
+    let kinds: Vec<&str> = [
+        ("slide", ops.slide),
+        ("switches", ops.switches),
+        ("swap", ops.swap),
+        ("block", ops.block),
+        ("reverse", ops.reverse),
+        ("trailing", ops.trailing),
+    ]
+    .iter()
+    .filter(|(_, on)| *on)
+    .map(|(kind, _)| *kind)
+    .collect();
+    if kinds.is_empty() {
+        return None;
+    }
+    // Picks from `lo..hi` leaving out `skip`, which must be in it.
+    let gen_except = |rng: &mut R, lo: usize, hi: usize, skip: usize| {
+        let i = rng.gen_range(lo, hi - 1);
+        if i >= skip {
+            i + 1
+        } else {
+            i
+        }
+    };
+    for _ in 0..RANDOM_TRIES {
+        let new_seq = match kinds[rng.gen_range(0, kinds.len())] {
+            // Like `Variations`, the first move stays where it is.
+            "slide" if n >= 3 => {
+                let from = rng.gen_range(1, n);
+                let (new_seq, count) = slide(seq, g, from, gen_except(rng, 1, n, from));
+                if count > 1 && !pushy {
+                    continue;
+                }
+                new_seq
+            }
+            "switches" => match Edits::switch_pairs(seq, gs).pick(rng) {
+                Some(new_seq) => new_seq,
+                None => continue,
+            },
+            "swap" if n >= 3 => {
+                let i = rng.gen_range(0, n - 2);
+                let j = rng.gen_range(i + 2, n);
+                if !can_pass(g, &seq[i..=i], &seq[i + 1..=j])
+                    || !can_pass(g, &seq[i..j], &seq[j..=j])
+                {
+                    continue;
+                }
+                let mut new_seq = seq.to_vec();
+                Edit::Swap(i, j).apply(seq, &mut new_seq);
+                new_seq
+            }
+            "block" if n >= 3 => {
+                let len = rng.gen_range(2, MAX_BLOCK.min(n - 1) + 1);
+                let from = rng.gen_range(0, n - len + 1);
+                let to = gen_except(rng, 0, n - len + 1, from);
+                let block = &seq[from..from + len];
+                if to < from && !can_pass(g, &seq[to..from], block)
+                    || to > from && !can_pass(g, block, &seq[from + len..to + len])
+                {
+                    continue;
+                }
+                let mut new_seq = Vec::with_capacity(n);
+                Edit::Block { from, len, to }.apply(seq, &mut new_seq);
+                new_seq
+            }
+            "reverse" if n >= 3 => {
+                let span = rng.gen_range(2, (MAX_REVERSE - 1).min(n - 1) + 1);
+                let i = rng.gen_range(0, n - span);
+                let j = i + span;
+                if !(i + 1..=j).all(|k| can_pass(g, &seq[i..k], &seq[k..=k])) {
+                    continue;
+                }
+                let mut new_seq = Vec::with_capacity(n);
+                Edit::Reverse(i, j).apply(seq, &mut new_seq);
+                new_seq
+            }
+            "trailing" => match Edits::trailing(seq, gs).pick(rng) {
+                Some(new_seq) => new_seq,
+                None => continue,
+            },
+            _ => continue,
+        };
+        if new_seq != seq {
+            return Some(new_seq);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{lvlups, opening, other_tower};
+
+    fn write_moves(name: &str, moves: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, moves).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_load_sequence() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+
+        let ok = write_moves("idlea_load_ok.txt", "0\n1\n0\n1\n");
+        let seq = load_sequence(&ok, &gs, false).unwrap();
+        assert_eq!(seq.len(), 4);
+        assert_eq!(seq[2], Move::LvlUp(LvlUp { uidx: 0, level: 3 }));
+
+        let too_soon = write_moves("idlea_load_soon.txt", &"0\n".repeat(4));
+        let err = load_sequence(&too_soon, &gs, false).unwrap_err();
+        assert!(err.to_string().starts_with("line 3:"), "{}", err);
+        assert_eq!(load_sequence(&too_soon, &gs, true).unwrap().len(), 2);
+
+        let mut maxed = GameState::new_from_game(&g);
+        maxed.levels[0] = 10;
+        let err = load_sequence(&ok, &maxed, false).unwrap_err();
+        assert!(err.to_string().contains("max is 10"), "{}", err);
+
+        let out_of_order = write_moves("idlea_load_order.txt", "2\n1\n");
+        let err = load_sequence(&out_of_order, &gs, false).unwrap_err();
+        assert!(err.to_string().starts_with("line 1:"), "{}", err);
+
+        let unknown = write_moves("idlea_load_unknown.txt", "0\n99\n");
+        assert!(load_sequence(&unknown, &gs, false).is_err());
+        assert!(load_sequence("no/such/file.txt", &gs, true).is_err());
+    }
+
+    #[test]
+    fn test_repair() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let seq = lvlups(&[(0, 2), (0, 4), (0, 3), (1, 2), (1, 1)]);
+        assert_eq!(repair(&seq, &gs), opening());
+        let fine = lvlups(&[(0, 2), (1, 1), (0, 3)]);
+        assert_eq!(repair(&fine, &gs), fine);
+    }
+
+    #[test]
+    fn test_switch_pairs() {
+        let g = other_tower();
+        let gs = GameState::new_from_game(&g);
+        let uidx = switchable(&g)[0];
+        let seq: Vec<Move> = (1..=3)
+            .map(|level| Move::LvlUp(LvlUp { uidx, level }))
+            .collect();
+        let switches = |seq: &[Move]| -> Vec<usize> {
+            seq.iter()
+                .filter_map(|mv| match mv {
+                    Move::Switch(sw) => Some(sw.iprod),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut added = Vec::new();
+        let mut pairs = Edits::switch_pairs(&seq, &gs);
+        while let Some(new_seq) = pairs.next() {
+            assert_eq!(switches(new_seq), vec![1, 0]);
+            assert_eq!(repair(new_seq, &gs), new_seq);
+            added.push(new_seq.to_vec());
+        }
+        // Out after level 1 and back after level 2 or 3, or out after 2 and back after 3.
+        assert_eq!(added.len(), 3);
+
+        let mut removed = 0;
+        let mut pairs = Edits::switch_pairs(&added[0], &gs);
+        while let Some(new_seq) = pairs.next() {
+            match switches(new_seq).len() {
+                0 => {
+                    assert_eq!(new_seq, &seq[..]);
+                    removed += 1;
+                }
+                n => assert_eq!(n, 4),
+            }
+        }
+        assert_eq!(removed, 1);
+    }
+
+    #[test]
+    fn test_operators_keep_prereqs() {
+        let g = other_tower();
+        let mut gs = GameState::new_from_game(&g);
+        gs.update_rates();
+        let in_order = |seq: &[Move]| {
+            let mut done = initial_done(&gs);
+            for mv in seq {
+                if !g
+                    .prereqs
+                    .get(mv)
+                    .map_or(true, |p| p.iter().all(|p| done.contains(p)))
+                {
+                    return false;
+                }
+                done.insert(*mv);
+            }
+            true
+        };
+        let mut scratch = GameState::new_from_game(&g);
+        scratch.copy_from(&gs);
+        let seq = crate::improve::random_play(&mut scratch, &vec![]);
+        assert!(in_order(&seq));
+
+        let sorted = |seq: &[Move]| {
+            let mut seq = seq.to_vec();
+            seq.sort_by_key(|mv| format!("{:?}", mv));
+            seq
+        };
+        let edits: Vec<(&str, Box<dyn VarIter>)> = vec![
+            ("swap", Box::from(Edits::swaps(&seq, &g))),
+            ("block", Box::from(Edits::blocks(&seq, &g))),
+            ("reverse", Box::from(Edits::reversals(&seq, &g))),
+        ];
+        for (name, mut variations) in edits {
+            let mut count = 0;
+            while let Some(new_seq) = variations.next() {
+                assert!(in_order(new_seq), "{} broke prerequisites", name);
+                assert_eq!(sorted(new_seq), sorted(&seq));
+                assert_ne!(new_seq, &seq[..]);
+                count += 1;
+            }
+            assert!(count > 0, "no {} variations", name);
+        }
+
+        // Levels that won't fit in before the event ends, after a short plan.
+        let short = &seq[..10];
+        let mut trailing = Edits::trailing(short, &gs);
+        let mut count = 0;
+        while let Some(new_seq) = trailing.next() {
+            assert!(in_order(new_seq));
+            assert_eq!(new_seq.len(), 11);
+            count += 1;
+        }
+        assert!(count > 0);
+
+        // Random variations are ones `variations` makes, and pushy slides keep prerequisites.
+        let all = Operators::from_arg("slide,switches,swap,block,reverse,trailing").unwrap();
+        let mut listed = HashSet::new();
+        let mut variations = variations(&seq, &gs, false, all);
+        while let Some(new_seq) = variations.next() {
+            listed.insert(new_seq.to_vec());
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            let new_seq = random_variation(&seq, &gs, false, all, &mut rng).unwrap();
+            assert!(listed.contains(&new_seq), "{:?}", new_seq);
+            let new_seq = random_variation(&seq, &gs, true, Operators::SLIDE_ONLY, &mut rng);
+            let new_seq = new_seq.unwrap();
+            assert!(in_order(&new_seq));
+            assert_eq!(sorted(&new_seq), sorted(&seq));
+        }
+
+        let ops = Operators::from_arg("slide,swap").unwrap();
+        assert!(ops.slide && ops.swap && !ops.switches);
+        assert!(Operators::from_arg("slide,twirl").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::process::Command;
+
+use serde_json::Value;
+
+/// Runs `idlea <cmd> --log-format jsonl` on the Other Tower event for a couple of seconds, and
+/// returns its output as events, checking that every line is one.
+fn run_jsonl(cmd: &str, extra: &[&str]) -> Vec<Value> {
+    let game = concat!(env!("CARGO_MANIFEST_DIR"), "/../g/other_tower.csv");
+    let out = Command::new(env!("CARGO_BIN_EXE_idlea"))
+        .args([cmd, "-g", game, "-b", "1", "--time-limit", "2"])
+        .args(["--log-format", "jsonl"])
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    stdout
+        .lines()
+        .map(|line| {
+            let event: Value = serde_json::from_str(line)
+                .unwrap_or_else(|err| panic!("{:?} is not JSON: {}", line, err));
+            assert!(event["event"].is_string(), "{:?} is not an event", line);
+            event
+        })
+        .collect()
+}
+
+#[test]
+fn test_rand_jsonl() {
+    let events = run_jsonl("rand", &["--seed", "1"]);
+    assert_eq!(events[0]["event"], "start");
+    assert_eq!(events[0]["seed"], 1);
+    assert_eq!(events.last().unwrap()["event"], "summary");
+}
+
+#[test]
+fn test_imp_jsonl() {
+    let plan = std::env::temp_dir().join("idlea_test_jsonl_plan.txt");
+    std::fs::write(&plan, "0\n1\n0\n1\n").unwrap();
+    let events = run_jsonl("imp", &[plan.to_str().unwrap()]);
+    assert_eq!(events[0]["event"], "start");
+    assert_eq!(events[0]["moves"], 4);
+    assert!(events.iter().any(|e| e["event"] == "depth"));
+}